@@ -0,0 +1,137 @@
+//! The LRU cache as it was before it moved onto a slab, kept as
+//! the baseline for the benchmark.
+//!
+//! Every new key gets its own boxed node, linked to the others with
+//! raw pointers, and the node is freed again when it is evicted.
+//! Only get and set are kept since that is all the benchmark uses.
+
+use data_structures::Cache;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ptr;
+
+/// A key-value node for a doubly linked list
+struct Node<K, V> {
+    key: K,
+    val: V,
+    next: *mut Node<K, V>,
+    prev: *mut Node<K, V>,
+}
+
+/// A cache that evicts least recently used nodes
+/// when exceeding given capacity
+pub struct BoxedLRUCache<K: Eq + Hash + Copy, V> {
+    capacity: usize,
+    page_map: HashMap<K, *mut Node<K, V>>,
+    front: *mut Node<K, V>,
+    back: *mut Node<K, V>,
+}
+
+impl<K, V> BoxedLRUCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    pub fn new(capacity: usize) -> BoxedLRUCache<K, V> {
+        assert!(capacity > 0, "cache capacity must be at least one");
+
+        BoxedLRUCache {
+            capacity,
+            page_map: HashMap::new(),
+            front: ptr::null_mut(),
+            back: ptr::null_mut(),
+        }
+    }
+
+    fn remove(&mut self, n: *mut Node<K, V>) {
+        unsafe {
+            if (*n).prev.is_null() {
+                self.back = (*n).next;
+            } else {
+                (*(*n).prev).next = (*n).next;
+            }
+
+            if (*n).next.is_null() {
+                self.front = (*n).prev;
+            } else {
+                (*(*n).next).prev = (*n).prev;
+            }
+        }
+    }
+
+    fn add_to_front(&mut self, n: *mut Node<K, V>) {
+        unsafe {
+            (*n).next = ptr::null_mut();
+            (*n).prev = self.front;
+
+            if self.back.is_null() {
+                self.back = n;
+            } else {
+                (*self.front).next = n;
+            }
+
+            self.front = n;
+        }
+    }
+
+    fn move_to_front(&mut self, n: *mut Node<K, V>) {
+        if n != self.front {
+            self.remove(n);
+            self.add_to_front(n);
+        }
+    }
+}
+
+impl<K, V> Cache<K, V> for BoxedLRUCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    fn get(&mut self, k: K) -> Option<V> {
+        let node = *self.page_map.get(&k)?;
+        self.move_to_front(node);
+        Some(unsafe { (*node).val.clone() })
+    }
+
+    fn set(&mut self, k: K, v: V) {
+        if let Some(&node) = self.page_map.get(&k) {
+            unsafe { (*node).val = v };
+            self.move_to_front(node);
+            return;
+        }
+
+        if self.page_map.len() == self.capacity {
+            let back = self.back;
+            self.remove(back);
+            let evicted = unsafe { Box::from_raw(back) };
+            self.page_map.remove(&evicted.key);
+        }
+
+        let node = Box::into_raw(Box::new(Node {
+            key: k,
+            val: v,
+            next: ptr::null_mut(),
+            prev: ptr::null_mut(),
+        }));
+        self.add_to_front(node);
+        self.page_map.insert(k, node);
+    }
+
+    fn contains(&self, k: &K) -> bool {
+        self.page_map.contains_key(k)
+    }
+
+    fn len(&self) -> usize {
+        self.page_map.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<K: Eq + Hash + Copy, V> Drop for BoxedLRUCache<K, V> {
+    fn drop(&mut self) {
+        for (_, node) in self.page_map.drain() {
+            unsafe { drop(Box::from_raw(node)) };
+        }
+    }
+}
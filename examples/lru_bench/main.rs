@@ -0,0 +1,97 @@
+//! Measures the slab-backed LRU cache against the boxed LRU cache
+//! it replaced and against the other cache policies, counting both
+//! time and allocator calls per operation.
+//!
+//! Run with `cargo run --release --example lru_bench`.
+
+extern crate data_structures;
+
+mod boxed;
+
+use boxed::BoxedLRUCache;
+use data_structures::{ARCCache, Cache, LFUCache, LRUCache, TwoQueueCache};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const CAPACITY: usize = 10_000;
+const OPERATIONS: u64 = 2_000_000;
+
+/// Counts every allocation so the report can show how often
+/// each cache goes to the allocator
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A xorshift generator so every cache sees the same keys
+struct Keys(u64);
+
+impl Keys {
+    fn next(&mut self, range: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % range
+    }
+}
+
+/// Fills the cache, then sets keys drawn from the given range,
+/// reading each one back first, and returns the time taken and
+/// the number of allocations after the warm up
+fn run<C: Cache<u64, u64>>(cache: &mut C, range: u64) -> (Duration, usize) {
+    for k in 0..CAPACITY as u64 {
+        cache.set(k, k);
+    }
+
+    let mut keys = Keys(0x2545_f491_4f6c_dd1d);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..OPERATIONS {
+        let k = keys.next(range);
+        if cache.get(k).is_none() {
+            cache.set(k, k);
+        }
+    }
+    (start.elapsed(), ALLOCATIONS.load(Ordering::Relaxed) - allocations)
+}
+
+fn report(name: &str, (elapsed, allocations): (Duration, usize)) {
+    let nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+    println!("  {:<14} {:>8.1} ns/op {:>8.3} allocs/op",
+             name,
+             nanos as f64 / OPERATIONS as f64,
+             allocations as f64 / OPERATIONS as f64);
+}
+
+fn main() {
+    let workloads = [("mostly hits", CAPACITY as u64 * 11 / 10),
+                     ("churn", CAPACITY as u64 * 100)];
+
+    for &(workload, range) in &workloads {
+        println!("{} (capacity {}, keys in 0..{}):", workload, CAPACITY, range);
+        report("boxed LRU", run(&mut BoxedLRUCache::new(CAPACITY), range));
+        report("LRUCache", run(&mut LRUCache::new(CAPACITY), range));
+        report("LFUCache", run(&mut LFUCache::new(CAPACITY), range));
+        report("ARCCache", run(&mut ARCCache::new(CAPACITY), range));
+        report("TwoQueueCache", run(&mut TwoQueueCache::new(CAPACITY), range));
+    }
+}
@@ -0,0 +1,246 @@
+//! An adaptive replacement cache (ARC).
+//!
+//! The cache is split into a list of entries seen once (T1) and
+//! a list of entries seen at least twice (T2). Keys evicted from
+//! each list are remembered without their values in ghost lists
+//! (B1 and B2). A hit in a ghost list means that list was too
+//! small, so the target size of T1 is moved towards it. Because
+//! entries seen only once never leave T1, a long scan over new
+//! keys cannot flush the frequently used entries in T2.
+
+use cache::{Cache, CapacityError};
+use key_list::KeyList;
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A cache that balances between evicting least recently used
+/// and least frequently used entries based on the workload
+pub struct ARCCache<K: Eq + Hash + Copy, V> {
+    capacity: usize,
+    /// The target size of T1
+    p: usize,
+    t1: KeyList<K>,
+    t2: KeyList<K>,
+    b1: KeyList<K>,
+    b2: KeyList<K>,
+    values: HashMap<K, V>,
+}
+
+impl<K, V> ARCCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    /// Create a new ARC cache with the given capacity
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize) -> ARCCache<K, V> {
+        assert!(capacity > 0, "{}", CapacityError);
+
+        ARCCache {
+            capacity,
+            p: 0,
+            t1: KeyList::new(),
+            t2: KeyList::new(),
+            b1: KeyList::new(),
+            b2: KeyList::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Create a new ARC cache with the given capacity or
+    /// return an error if the capacity is zero
+    pub fn try_new(capacity: usize) -> Result<ARCCache<K, V>, CapacityError> {
+        if capacity == 0 {
+            Err(CapacityError)
+        } else {
+            Ok(ARCCache::new(capacity))
+        }
+    }
+
+    /// Evicts the least recently used entry of either T1 or T2
+    /// into its ghost list depending on the target size of T1
+    fn replace(&mut self, in_b2: bool) {
+        let t1_len = self.t1.len();
+        let from_t1 = t1_len > 0 &&
+                      (t1_len > self.p || (in_b2 && t1_len == self.p) || self.t2.len() == 0);
+
+        if from_t1 {
+            let k = self.t1.pop_back().unwrap();
+            self.values.remove(&k);
+            self.b1.push_front(k);
+        } else if let Some(k) = self.t2.pop_back() {
+            self.values.remove(&k);
+            self.b2.push_front(k);
+        }
+    }
+}
+
+impl<K, V> Cache<K, V> for ARCCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    fn get(&mut self, k: K) -> Option<V> {
+        if self.t1.remove(&k) || self.t2.remove(&k) {
+            self.t2.push_front(k);
+            self.values.get(&k).cloned()
+        } else {
+            None
+        }
+    }
+
+    fn set(&mut self, k: K, v: V) {
+        // Cache hit: the entry has now been seen at least twice
+        if self.t1.remove(&k) || self.t2.remove(&k) {
+            self.t2.push_front(k);
+            self.values.insert(k, v);
+            return;
+        }
+
+        if self.b1.remove(&k) {
+            // T1 was evicted too early, so grow its target size
+            let delta = cmp::max(self.b2.len() / (self.b1.len() + 1), 1);
+            self.p = cmp::min(self.p + delta, self.capacity);
+            self.replace(false);
+            self.t2.push_front(k);
+        } else if self.b2.remove(&k) {
+            // T2 was evicted too early, so shrink the target size of T1
+            let delta = cmp::max(self.b1.len() / (self.b2.len() + 1), 1);
+            self.p = self.p.saturating_sub(delta);
+            self.replace(true);
+            self.t2.push_front(k);
+        } else {
+            let l1 = self.t1.len() + self.b1.len();
+            if l1 == self.capacity {
+                if self.t1.len() < self.capacity {
+                    self.b1.pop_back();
+                    self.replace(false);
+                } else {
+                    let evicted = self.t1.pop_back().unwrap();
+                    self.values.remove(&evicted);
+                }
+            } else {
+                let total = l1 + self.t2.len() + self.b2.len();
+                if total >= self.capacity {
+                    if total == 2 * self.capacity {
+                        self.b2.pop_back();
+                    }
+                    self.replace(false);
+                }
+            }
+            self.t1.push_front(k);
+        }
+
+        self.values.insert(k, v);
+    }
+
+    fn contains(&self, k: &K) -> bool {
+        self.values.contains_key(k)
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cache::Cache;
+    use lru_cache::LRUCache;
+    use super::*;
+
+    /// Sets 1 and 2 and reads them back so they are frequently used
+    fn warm<C: Cache<i32, i32>>(cache: &mut C) {
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(1), Some(1));
+        assert_eq!(cache.get(2), Some(2));
+    }
+
+    fn scan<C: Cache<i32, i32>>(cache: &mut C) {
+        for i in 100..200 {
+            cache.set(i, i);
+        }
+    }
+
+    #[test]
+    fn test_scan_does_not_flush_frequent_list() {
+        let mut cache = ARCCache::new(4);
+        warm(&mut cache);
+        scan(&mut cache);
+
+        assert_eq!(cache.t2.len(), 2);
+        assert_eq!(cache.get(1), Some(1));
+        assert_eq!(cache.get(2), Some(2));
+        assert_eq!(cache.len(), 4);
+
+        // The same access pattern flushes a LRU cache
+        let mut lru = LRUCache::new(4);
+        warm(&mut lru);
+        scan(&mut lru);
+        assert_eq!(lru.get(1), None);
+        assert_eq!(lru.get(2), None);
+    }
+
+    #[test]
+    fn test_ghost_hit_in_b1_grows_t1() {
+        let mut cache = ARCCache::new(2);
+        warm(&mut cache);
+        cache.set(3, 3);
+        cache.set(4, 4);
+        assert!(cache.b1.contains(&3));
+        assert_eq!(cache.p, 0);
+
+        // 3 was evicted from T1 while T2 kept its entries
+        cache.set(3, 3);
+        assert_eq!(cache.p, 1);
+        assert!(cache.t2.contains(&3));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_ghost_hit_in_b2_shrinks_t1() {
+        let mut cache = ARCCache::new(2);
+        warm(&mut cache);
+        cache.set(3, 3);
+        cache.set(3, 3);
+        assert!(cache.b2.contains(&1));
+
+        cache.p = 2;
+        cache.set(1, 1);
+        assert_eq!(cache.p, 1);
+        assert!(cache.t2.contains(&1));
+    }
+
+    #[test]
+    fn test_ghost_lists_stay_bounded() {
+        let mut cache = ARCCache::new(8);
+        for round in 0..5 {
+            for i in 0..40 {
+                cache.set(i, i);
+                if i % (round + 2) == 0 {
+                    cache.get(i);
+                }
+            }
+            assert!(cache.len() <= 8);
+            assert!(cache.t1.len() + cache.b1.len() <= 8);
+            assert!(cache.t1.len() + cache.t2.len() + cache.b1.len() + cache.b2.len() <= 16);
+        }
+    }
+
+    #[test]
+    fn test_zero_capacity_is_rejected() {
+        assert_eq!(ARCCache::<i32, i32>::try_new(0).err(), Some(CapacityError));
+        assert!(ARCCache::<i32, i32>::try_new(1).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "cache capacity must be at least one")]
+    fn test_new_panics_on_zero_capacity() {
+        ARCCache::<i32, i32>::new(0);
+    }
+}
@@ -1,47 +1,96 @@
 //! A deque implementation using a mutable
-//! pointer container UnsafeCell with a TypedArena.
+//! pointer container UnsafeCell with a typed_arena::Arena.
 //!
 //! It is safer than raw pointers because although the
 //! UnsafeCell operations are unsafe, you won't have to
 //! worry about use after free because the deque and all of
-//! the nodes have the lifetime of the parent TypedArena.
+//! the nodes have the lifetime of the parent arena.
+//!
+//! Values can't be moved out of the arena, so each node keeps
+//! its data in an Option slot that pop takes the value out of.
+//!
+//! The arena only frees memory when it is dropped, so popped and
+//! removed nodes go onto an intrusive free list linked through
+//! their prev pointers and the next push reuses them. A deque
+//! that is pushed and popped forever only takes as many nodes
+//! from the arena as it ever held at once.
 
-use arena::TypedArena;
+use typed_arena::Arena;
 use std::cell::UnsafeCell;
+use std::mem;
+use std::ptr;
 
 pub struct Node<'a, T: 'a> {
-    data: T,
+    /// None once the data has been popped or removed
+    data: UnsafeCell<Option<T>>,
     next: UnsafeCell<Option<&'a Node<'a, T>>>,
     prev: UnsafeCell<Option<&'a Node<'a, T>>>,
 }
 
 impl<'a, T: 'a> Node<'a, T> {
-    pub fn new<'b>(data: T, arena: &'b TypedArena<Node<'b, T>>) -> &'b Node<'b, T> {
+    pub fn new<'b>(data: T, arena: &'b Arena<Node<'b, T>>) -> &'b Node<'b, T> {
         arena.alloc(Node {
-            data: data,
+            data: UnsafeCell::new(Some(data)),
             next: UnsafeCell::new(None),
             prev: UnsafeCell::new(None),
         })
     }
+
+    /// Returns the data of a node that is still in a deque
+    fn data(&self) -> &T {
+        unsafe { (*self.data.get()).as_ref().expect("node was already popped") }
+    }
+
+    /// Moves the data out of a node that is being removed
+    fn take_data(&self) -> T {
+        unsafe { (*self.data.get()).take().expect("node was already popped") }
+    }
 }
 
 pub struct Deque<'a, T: 'a> {
-    arena: &'a TypedArena<Node<'a, T>>,
+    arena: &'a Arena<Node<'a, T>>,
     front: UnsafeCell<Option<&'a Node<'a, T>>>,
     back: UnsafeCell<Option<&'a Node<'a, T>>>,
+    /// Emptied nodes waiting to be reused, linked through prev
+    free: Option<&'a Node<'a, T>>,
+    len: usize,
 }
 
-impl<'a, T: Clone> Deque<'a, T> {
-    pub fn new(arena: &'a TypedArena<Node<'a, T>>) -> Deque<'a, T> {
+impl<'a, T> Deque<'a, T> {
+    pub fn new(arena: &'a Arena<Node<'a, T>>) -> Deque<'a, T> {
         Deque {
-            arena: arena,
+            arena,
             front: UnsafeCell::new(None),
             back: UnsafeCell::new(None),
+            free: None,
+            len: 0,
+        }
+    }
+
+    /// Takes a node off the free list, or allocates one in the
+    /// arena when the free list is empty
+    fn alloc_node(&mut self, data: T) -> &'a Node<'a, T> {
+        match self.free {
+            Some(node) => unsafe {
+                self.free = (*node.prev.get()).take();
+                (*node.data.get()) = Some(data);
+                node
+            },
+            None => Node::new(data, self.arena),
+        }
+    }
+
+    /// Puts an unlinked node whose data has been taken on the free list
+    fn free_node(&mut self, node: &'a Node<'a, T>) {
+        unsafe {
+            (*node.next.get()) = None;
+            (*node.prev.get()) = self.free;
         }
+        self.free = Some(node);
     }
 
     fn init_node(&mut self, data: T) {
-        let init_node = Node::new(data, self.arena);
+        let init_node = self.alloc_node(data);
 
         // Set both the front and back pointers to the initial node
         unsafe {
@@ -51,11 +100,12 @@ impl<'a, T: Clone> Deque<'a, T> {
     }
 
     pub fn push_back(&mut self, data: T) {
+        self.len += 1;
         unsafe {
             if (*self.front.get()).is_none() {
                 self.init_node(data);
             } else if let Some(old_back) = *self.back.get() {
-                let new_back = Node::new(data, self.arena);
+                let new_back = self.alloc_node(data);
 
                 // Set the new back pointer and old back pointer references
                 (*new_back.next.get()) = Some(old_back);
@@ -67,9 +117,10 @@ impl<'a, T: Clone> Deque<'a, T> {
     }
 
     pub fn push_front(&mut self, data: T) {
+        self.len += 1;
         unsafe {
             if let Some(old_front) = *self.front.get() {
-                let new_front = Node::new(data, self.arena);
+                let new_front = self.alloc_node(data);
 
                 // Set the new front pointer and old front pointer references
                 (*new_front.prev.get()) = Some(old_front);
@@ -84,52 +135,155 @@ impl<'a, T: Clone> Deque<'a, T> {
 
     pub fn pop_front(&mut self) -> Option<T> {
         unsafe {
-            if let Some(old_front) = *self.front.get() {
-                (*self.front.get()) = *old_front.prev.get();
-                return Some(old_front.data.clone());
+            let old_front = (*self.front.get())?;
+            (*self.front.get()) = (*old_front.prev.get()).take();
+            match *self.front.get() {
+                Some(new_front) => (*new_front.next.get()) = None,
+                None => (*self.back.get()) = None,
             }
+            self.len -= 1;
+            let data = old_front.take_data();
+            self.free_node(old_front);
+            Some(data)
         }
-
-        None
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
         unsafe {
-            if let Some(old_back) = *self.back.get() {
-                (*self.back.get()) = *old_back.next.get();
-                return Some(old_back.data.clone());
+            let old_back = (*self.back.get())?;
+            (*self.back.get()) = (*old_back.next.get()).take();
+            match *self.back.get() {
+                Some(new_back) => (*new_back.prev.get()) = None,
+                None => (*self.front.get()) = None,
             }
+            self.len -= 1;
+            let data = old_back.take_data();
+            self.free_node(old_back);
+            Some(data)
         }
+    }
 
-        None
+    pub fn len(&self) -> usize {
+        self.len
     }
 
     pub fn is_empty(&self) -> bool {
         unsafe { (*self.front.get()).is_none() }
     }
+
+    /// Moves every element of other to the back of this deque in
+    /// O(1), leaving other empty. Both deques have to live in
+    /// arenas with the same lifetime
+    pub fn append(&mut self, other: &mut Deque<'a, T>) {
+        unsafe {
+            if let Some(other_front) = (*other.front.get()).take() {
+                match *self.back.get() {
+                    Some(old_back) => {
+                        (*old_back.prev.get()) = Some(other_front);
+                        (*other_front.next.get()) = Some(old_back);
+                    }
+                    None => (*self.front.get()) = Some(other_front),
+                }
+                (*self.back.get()) = (*other.back.get()).take();
+            }
+        }
+        self.len += mem::replace(&mut other.len, 0);
+    }
+
+    /// Splits the deque in two at the given index and returns the
+    /// elements from the index on, allocated in the same arena.
+    /// Panics if the index is greater than the length
+    pub fn split_off(&mut self, at: usize) -> Deque<'a, T> {
+        let mut split = Deque::new(self.arena);
+        assert!(at <= self.len, "split index out of bounds");
+        if at == 0 {
+            mem::swap(&mut self.front, &mut split.front);
+            mem::swap(&mut self.back, &mut split.back);
+            mem::swap(&mut self.len, &mut split.len);
+            return split;
+        }
+
+        unsafe {
+            // Find the last node that stays in this deque
+            let mut last = (*self.front.get()).unwrap();
+            for _ in 1..at {
+                last = (*last.prev.get()).unwrap();
+            }
+
+            if let Some(split_front) = (*last.prev.get()).take() {
+                (*split_front.next.get()) = None;
+                (*split.front.get()) = Some(split_front);
+                (*split.back.get()) = (*self.back.get()).replace(last);
+            }
+        }
+        split.len = self.len - at;
+        self.len = at;
+        split
+    }
+
+    /// Reverses the order of the elements in place
+    pub fn reverse(&mut self) {
+        unsafe {
+            let mut link = *self.front.get();
+            while let Some(node) = link {
+                ptr::swap(node.next.get(), node.prev.get());
+                link = *node.next.get();
+            }
+        }
+        mem::swap(&mut self.front, &mut self.back);
+    }
+
+    /// Removes every element for which the predicate returns false
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        unsafe {
+            let mut link = *self.front.get();
+            while let Some(node) = link {
+                link = *node.prev.get();
+                if f(node.data()) {
+                    continue;
+                }
+
+                let toward_front = *node.next.get();
+                let toward_back = *node.prev.get();
+                match toward_front {
+                    Some(other) => (*other.prev.get()) = toward_back,
+                    None => (*self.front.get()) = toward_back,
+                }
+                match toward_back {
+                    Some(other) => (*other.next.get()) = toward_front,
+                    None => (*self.back.get()) = toward_front,
+                }
+                node.take_data();
+                self.free_node(node);
+                self.len -= 1;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use arena::TypedArena;
+    use typed_arena::Arena;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
     use super::*;
 
     #[test]
     fn test_empty() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
 
         let empty_deque = Deque::new(&arena);
-        assert_eq!(empty_deque.is_empty(), true);
+        assert!(empty_deque.is_empty());
 
         let mut nonempty_deque = Deque::new(&arena);
         nonempty_deque.push_back(1);
         nonempty_deque.push_back(2);
-        assert_eq!(nonempty_deque.is_empty(), false);
+        assert!(!nonempty_deque.is_empty());
     }
 
     #[test]
     fn test_push_back() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
         let mut deque = Deque::new(&arena);
 
         deque.push_back(1);
@@ -142,7 +296,7 @@ mod tests {
 
     #[test]
     fn test_push_front() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
         let mut deque = Deque::new(&arena);
 
         deque.push_front(1);
@@ -153,9 +307,97 @@ mod tests {
         assert_eq!(deque.pop_front(), None);
     }
 
+    /// Walks the links in both directions and checks they agree
+    fn elems(deque: &Deque<i32>) -> Vec<i32> {
+        let mut forward = vec![];
+        let mut backward = vec![];
+        unsafe {
+            let mut link = *deque.front.get();
+            while let Some(node) = link {
+                forward.push(*node.data());
+                link = *node.prev.get();
+            }
+            let mut link = *deque.back.get();
+            while let Some(node) = link {
+                backward.push(*node.data());
+                link = *node.next.get();
+            }
+        }
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!(forward.len(), deque.len());
+        forward
+    }
+
+    fn deque_from<'a>(arena: &'a Arena<Node<'a, i32>>, elems: &[i32]) -> Deque<'a, i32> {
+        let mut deque = Deque::new(arena);
+        for &elem in elems {
+            deque.push_back(elem);
+        }
+        deque
+    }
+
+    #[test]
+    fn test_append() {
+        let arena = Arena::new();
+        let mut deque = deque_from(&arena, &[1, 2]);
+        let mut other = deque_from(&arena, &[3, 4]);
+
+        deque.append(&mut other);
+        assert_eq!(elems(&deque), vec![1, 2, 3, 4]);
+        assert!(other.is_empty());
+
+        other.append(&mut deque);
+        assert_eq!(elems(&other), vec![1, 2, 3, 4]);
+        assert_eq!(elems(&deque), vec![]);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let arena = Arena::new();
+        for at in 0..6 {
+            let mut deque = deque_from(&arena, &[0, 1, 2, 3, 4]);
+            let split = deque.split_off(at);
+            let expected: Vec<_> = (0..5).collect();
+            assert_eq!(elems(&deque), &expected[..at]);
+            assert_eq!(elems(&split), &expected[at..]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_off_out_of_bounds() {
+        let arena = Arena::new();
+        deque_from(&arena, &[1, 2]).split_off(3);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let arena = Arena::new();
+        let mut deque = deque_from(&arena, &[1, 2, 3]);
+        deque.reverse();
+        assert_eq!(elems(&deque), vec![3, 2, 1]);
+        deque.push_front(4);
+        deque.push_back(0);
+        assert_eq!(elems(&deque), vec![4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_retain() {
+        let arena = Arena::new();
+        let mut deque = deque_from(&arena, &[1, 2, 3, 4, 5, 6]);
+        deque.retain(|&elem| elem % 2 == 0);
+        assert_eq!(elems(&deque), vec![2, 4, 6]);
+        deque.retain(|&elem| elem > 4);
+        assert_eq!(elems(&deque), vec![6]);
+        deque.retain(|_| false);
+        assert_eq!(elems(&deque), vec![]);
+        assert!(deque.is_empty());
+    }
+
     #[test]
     fn test_pop_back() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
         let mut deque = Deque::new(&arena);
 
         deque.push_back(1);
@@ -164,4 +406,142 @@ mod tests {
         assert_eq!(deque.pop_back(), Some(1));
         assert_eq!(deque.pop_back(), None);
     }
+
+    #[test]
+    fn test_drained_deque_is_empty() {
+        let arena = Arena::new();
+        let mut deque = Deque::new(&arena);
+
+        deque.push_back(1);
+        deque.push_back(2);
+        assert_eq!(deque.pop_front(), Some(1));
+        assert_eq!(deque.pop_front(), Some(2));
+        assert!(deque.is_empty());
+        assert_eq!(deque.pop_back(), None);
+
+        // Pushing onto a drained deque doesn't link to popped nodes
+        deque.push_back(3);
+        deque.push_front(4);
+        assert_eq!(elems(&deque), vec![4, 3]);
+    }
+
+    #[test]
+    fn test_pop_both_ends() {
+        let arena = Arena::new();
+        let mut deque = deque_from(&arena, &[1, 2, 3]);
+
+        assert_eq!(deque.pop_front(), Some(1));
+        assert_eq!(deque.pop_back(), Some(3));
+        assert_eq!(deque.len(), 1);
+        assert_eq!(deque.pop_back(), Some(2));
+        assert_eq!(deque.pop_front(), None);
+        assert_eq!(deque.pop_back(), None);
+        assert!(deque.is_empty());
+    }
+
+    #[test]
+    fn test_interleaved_against_vec_deque() {
+        let arena = Arena::new();
+        let mut deque = Deque::new(&arena);
+        let mut expected = VecDeque::new();
+
+        // Deterministic xorshift so the test doesn't need a rand crate
+        let mut state: u32 = 0x9e37_79b9;
+        for i in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            match state % 4 {
+                0 => {
+                    deque.push_front(i);
+                    expected.push_front(i);
+                }
+                1 => {
+                    deque.push_back(i);
+                    expected.push_back(i);
+                }
+                2 => assert_eq!(deque.pop_front(), expected.pop_front()),
+                _ => assert_eq!(deque.pop_back(), expected.pop_back()),
+            }
+            assert_eq!(deque.len(), expected.len());
+            assert_eq!(deque.is_empty(), expected.is_empty());
+        }
+        assert_eq!(elems(&deque), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_steady_state_reuses_nodes() {
+        let arena = Arena::new();
+        let mut deque = deque_from(&arena, &[0, 1, 2, 3]);
+        let mut expected: VecDeque<_> = (0..4).collect();
+        assert_eq!(arena.len(), 4);
+
+        for i in 4..10_000 {
+            if i % 2 == 0 {
+                deque.push_back(i);
+                expected.push_back(i);
+                assert_eq!(deque.pop_front(), expected.pop_front());
+            } else {
+                deque.push_front(i);
+                expected.push_front(i);
+                assert_eq!(deque.pop_back(), expected.pop_back());
+            }
+        }
+        assert_eq!(elems(&deque), expected.into_iter().collect::<Vec<_>>());
+
+        // The deque peaks at five elements between a push and a pop
+        assert_eq!(arena.len(), 5);
+    }
+
+    #[test]
+    fn test_freed_nodes_are_reused_up_to_peak() {
+        let arena = Arena::new();
+        let mut deque = Deque::new(&arena);
+
+        for round in 0..100 {
+            for i in 0..8 {
+                deque.push_back(round * 8 + i);
+            }
+            deque.retain(|&elem| elem % 2 == 0);
+            while deque.pop_front().is_some() {}
+            assert!(deque.is_empty());
+        }
+        assert_eq!(arena.len(), 8);
+
+        // Recycled nodes don't keep stale links to each other
+        deque.push_back(1);
+        deque.push_back(2);
+        deque.push_front(0);
+        assert_eq!(elems(&deque), vec![0, 1, 2]);
+        assert_eq!(arena.len(), 8);
+    }
+
+    #[test]
+    fn test_values_without_clone() {
+        struct NoClone {
+            _rc: Rc<()>,
+        }
+
+        let rc = Rc::new(());
+        {
+            let arena = Arena::new();
+            let mut deque = Deque::new(&arena);
+            for _ in 0..4 {
+                deque.push_back(NoClone { _rc: rc.clone() });
+            }
+
+            // Popped values are owned and dropped by the caller
+            drop(deque.pop_front());
+            drop(deque.pop_back());
+            assert_eq!(Rc::strong_count(&rc), 3);
+            deque.retain(|_| false);
+            assert_eq!(Rc::strong_count(&rc), 1);
+
+            deque.push_front(NoClone { _rc: rc.clone() });
+            assert_eq!(Rc::strong_count(&rc), 2);
+        }
+
+        // Values still in the deque are dropped with the arena
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
 }
@@ -1,28 +1,29 @@
 //! A graph implementation using a mutable
-//! pointer container UnsafeCell with a TypedArena.
+//! pointer container UnsafeCell with a typed_arena::Arena.
 //!
 //! It is safer than raw pointers because although the
 //! UnsafeCell operations are unsafe, you won't have to
 //! worry about use after free because the deque and all of
-//! the nodes have the lifetime of the parent TypedArena.
+//! the nodes have the lifetime of the parent arena.
 
-use arena::TypedArena;
+use typed_arena::Arena;
 use std::cell::UnsafeCell;
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
-use std::i32;
 
 pub struct Node<'a, T: 'a> {
     id: i32,
+    // Only the tests read the data so far
+    #[allow(dead_code)]
     data: T,
     edges: UnsafeCell<Vec<(i32, &'a Node<'a, T>)>>,
 }
 
 impl<'a, T> Node<'a, T> {
-    pub fn new<'b>(id: i32, data: T, arena: &'b TypedArena<Node<'b, T>>) -> &'b Node<'b, T> {
+    pub fn new<'b>(id: i32, data: T, arena: &'b Arena<Node<'b, T>>) -> &'b Node<'b, T> {
         arena.alloc(Node {
-            id: id,
-            data: data,
+            id,
+            data,
             edges: UnsafeCell::new(Vec::new()),
         })
     }
@@ -34,6 +35,8 @@ pub struct NodeState {
     cost: i32,
 }
 
+// BinaryHeap only orders by Ord, which puts the cheapest state first
+#[allow(clippy::derive_ord_xor_partial_ord)]
 impl Ord for NodeState {
     fn cmp(&self, other: &NodeState) -> Ordering {
         other.cost.cmp(&self.cost)
@@ -42,19 +45,19 @@ impl Ord for NodeState {
 
 pub struct Graph<'a, T: 'a> {
     pub root: i32,
-    arena: &'a TypedArena<Node<'a, T>>,
+    arena: &'a Arena<Node<'a, T>>,
     id_map: HashMap<i32, &'a Node<'a, T>>,
     curr_id: i32,
 }
 
 impl<'a, T: Clone> Graph<'a, T> {
-    pub fn new(data: T, arena: &'a TypedArena<Node<'a, T>>) -> Graph<'a, T> {
+    pub fn new(data: T, arena: &'a Arena<Node<'a, T>>) -> Graph<'a, T> {
         let mut id_map = HashMap::new();
         id_map.insert(0, Node::new(0, data, arena));
 
         Graph {
-            arena: arena,
-            id_map: id_map,
+            arena,
+            id_map,
             root: 0,
             curr_id: 1,
         }
@@ -91,7 +94,7 @@ impl<'a, T: Clone> Graph<'a, T> {
         }
 
         while let Some(node) = queue.pop_front() {
-            func(&node);
+            func(node);
             explored_nodes.insert(node.id);
 
             for &(_, edge) in unsafe { &*node.edges.get() } {
@@ -113,7 +116,7 @@ impl<'a, T: Clone> Graph<'a, T> {
         }
 
         while let Some(node) = stack.pop() {
-            func(&node);
+            func(node);
             explored_nodes.insert(node.id);
 
             for &(_, edge) in unsafe { &*node.edges.get() } {
@@ -133,7 +136,7 @@ impl<'a, T: Clone> Graph<'a, T> {
         let mut visited = HashMap::new();
 
         // Initialize distances of nodes to 'infinity' and the previous link to None
-        self.bfs_map(|ref node| {
+        self.bfs_map(|node| {
             dist.insert(node.id, i32::MAX);
             prev.insert(node.id, None);
         });
@@ -191,12 +194,13 @@ impl<'a, T: Clone> Graph<'a, T> {
 
 #[cfg(test)]
 mod tests {
-    use arena::TypedArena;
+    use typed_arena::Arena;
     use super::*;
 
     #[test]
+    #[allow(clippy::clone_on_copy, clippy::needless_borrow)]
     fn test_bfs_map() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
         let mut graph = Graph::new(2, &arena);
 
         let three_node = graph.add_node(3);
@@ -216,8 +220,9 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::clone_on_copy, clippy::needless_borrow)]
     fn test_dfs_map() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
         let mut graph = Graph::new(2, &arena);
 
         let three_node = graph.add_node(3);
@@ -240,7 +245,7 @@ mod tests {
 
     #[test]
     fn test_dijkstra() {
-        let arena = TypedArena::new();
+        let arena = Arena::new();
         let mut graph = Graph::new(2, &arena);
 
         let two_node = graph.root;
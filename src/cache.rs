@@ -0,0 +1,82 @@
+//! A common interface for caches with different eviction policies.
+//!
+//! Code that only talks to a cache through this trait can swap
+//! the LRU cache for the LFU, ARC or 2Q caches (or the other way
+//! around) without changing any call sites.
+
+use std::error::Error;
+use std::fmt;
+
+/// The error returned when creating a cache with a capacity of zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityError;
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cache capacity must be at least one")
+    }
+}
+
+impl Error for CapacityError {}
+
+/// A key-value cache with a fixed capacity
+///
+/// Every cache has room for at least one entry. Their new
+/// constructors panic on a capacity of zero and their try_new
+/// constructors return a CapacityError instead.
+pub trait Cache<K, V> {
+    /// Retrieves and returns the value for the given key
+    fn get(&mut self, k: K) -> Option<V>;
+
+    /// Sets a key value pair in the cache, evicting an item
+    /// chosen by the cache's policy if it is full
+    fn set(&mut self, k: K, v: V);
+
+    /// Checks if the key is in the cache without counting it
+    /// as an access
+    fn contains(&self, k: &K) -> bool;
+
+    /// The number of items in the cache
+    fn len(&self) -> usize;
+
+    /// The maximum number of items before evicting
+    fn capacity(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arc_cache::ARCCache;
+    use lfu_cache::LFUCache;
+    use lru_cache::LRUCache;
+    use two_queue_cache::TwoQueueCache;
+    use super::*;
+
+    fn fill_and_read<C: Cache<i32, i32>>(cache: &mut C) {
+        for i in 0..10 {
+            cache.set(i, i * 10);
+        }
+        assert_eq!(cache.len(), cache.capacity());
+
+        for i in 0..10 {
+            if let Some(v) = cache.get(i) {
+                assert_eq!(v, i * 10);
+                assert!(cache.contains(&i));
+            }
+        }
+
+        cache.set(9, 900);
+        assert_eq!(cache.get(9), Some(900));
+    }
+
+    #[test]
+    fn test_policies_are_interchangeable() {
+        fill_and_read(&mut LRUCache::new(4));
+        fill_and_read(&mut LFUCache::new(4));
+        fill_and_read(&mut ARCCache::new(4));
+        fill_and_read(&mut TwoQueueCache::new(4));
+    }
+}
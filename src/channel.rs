@@ -0,0 +1,495 @@
+//! A blocking multi producer, single consumer channel.
+//!
+//! Messages are kept in one of the crate's queues behind a Mutex:
+//! a two stack Queue when the channel is unbounded, or a RingBuffer
+//! when it is bounded. The receiver waits on a Condvar until a
+//! message arrives or the last sender is dropped, and senders of a
+//! bounded channel wait on a second Condvar until there is room.
+//! The channel keeps a count of live senders and whether the
+//! receiver is alive so that each side can tell when the other
+//! side has disconnected.
+
+use queue::Queue;
+use ring_buffer::RingBuffer;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+enum Buffer<T> {
+    Unbounded(Queue<T>),
+    Bounded(RingBuffer<T>),
+}
+
+impl<T> Buffer<T> {
+    fn push(&mut self, data: T) -> Result<(), T> {
+        match *self {
+            Buffer::Unbounded(ref mut queue) => {
+                queue.enqueue(data);
+                Ok(())
+            }
+            Buffer::Bounded(ref mut ring) => ring.try_push(data),
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        match *self {
+            Buffer::Unbounded(ref mut queue) => queue.dequeue(),
+            Buffer::Bounded(ref mut ring) => ring.pop(),
+        }
+    }
+}
+
+struct State<T> {
+    buffer: Buffer<T>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signaled when a message is sent or the last sender is dropped
+    not_empty: Condvar,
+    /// Signaled when a message is received or the receiver is dropped
+    not_full: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // Neither side panics while holding the lock, so the state
+        // is consistent even if the mutex was poisoned
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The sending half of a channel, which can be cloned to send
+/// from several threads
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The receiving half of a channel
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a channel that buffers any number of messages
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    new_channel(Buffer::Unbounded(Queue::new()))
+}
+
+/// Creates a channel that buffers up to bound messages, after which
+/// senders block until the receiver catches up. Panics if the bound
+/// is zero
+pub fn sync_channel<T>(bound: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(bound > 0, "channel bound must be at least one");
+    new_channel(Buffer::Bounded(RingBuffer::new(bound)))
+}
+
+fn new_channel<T>(buffer: Buffer<T>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer,
+            senders: 1,
+            receiver_alive: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+impl<T> Sender<T> {
+    /// Sends a message, blocking while a bounded channel is full.
+    /// Fails and hands the message back if the receiver was dropped
+    pub fn send(&self, data: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        let mut data = data;
+        loop {
+            if !state.receiver_alive {
+                return Err(SendError(data));
+            }
+            match state.buffer.push(data) {
+                Ok(()) => break,
+                Err(rejected) => {
+                    data = rejected;
+                    state = self.shared.not_full.wait(state).unwrap_or_else(|err| err.into_inner());
+                }
+            }
+        }
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Sends a message without blocking
+    pub fn try_send(&self, data: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive {
+            return Err(TrySendError::Disconnected(data));
+        }
+        state.buffer.push(data).map_err(TrySendError::Full)?;
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives a message, blocking until one arrives. Fails once
+    /// every sender is dropped and no messages are left
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(data) = state.buffer.pop() {
+                drop(state);
+                self.shared.not_full.notify_one();
+                return Ok(data);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.shared.not_empty.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// Receives a message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.buffer.pop() {
+            Some(data) => {
+                drop(state);
+                self.shared.not_full.notify_one();
+                Ok(data)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Receives a message, blocking for at most the timeout
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout too large to fit in an Instant never runs out
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(data) = state.buffer.pop() {
+                drop(state);
+                self.shared.not_full.notify_one();
+                return Ok(data);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    self.shared
+                        .not_empty
+                        .wait_timeout(state, deadline - now)
+                        .map(|(state, _)| state)
+                        .unwrap_or_else(|err| err.into_inner().0)
+                }
+                None => self.shared.not_empty.wait(state).unwrap_or_else(|err| err.into_inner()),
+            };
+        }
+    }
+
+    /// Returns an iterator that receives messages until every
+    /// sender is dropped
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_alive = false;
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// Blocking iterator over the messages of a receiver
+pub struct Iter<'a, T: 'a> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Returned by send when the receiver was dropped, with the
+/// message that could not be sent
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sending on a channel whose receiver was dropped")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+/// Returned by try_send with the message that could not be sent
+#[derive(PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrySendError::Full(_) => write!(f, "Full(..)"),
+            TrySendError::Disconnected(_) => write!(f, "Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrySendError::Full(_) => write!(f, "sending on a full channel"),
+            TrySendError::Disconnected(_) => {
+                write!(f, "sending on a channel whose receiver was dropped")
+            }
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+/// Returned by recv when every sender was dropped and the
+/// channel is empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "receiving on an empty channel whose senders were dropped")
+    }
+}
+
+impl Error for RecvError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Disconnected => {
+                write!(f, "receiving on an empty channel whose senders were dropped")
+            }
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecvTimeoutError::Timeout => write!(f, "timed out waiting on the channel"),
+            RecvTimeoutError::Disconnected => {
+                write!(f, "receiving on an empty channel whose senders were dropped")
+            }
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_channel() {
+        let (sender, receiver) = channel();
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.try_recv(), Ok(2));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_disconnect_after_last_sender_dropped() {
+        let (sender, receiver) = channel();
+        let other = sender.clone();
+        sender.send(1).unwrap();
+        drop(sender);
+        other.send(2).unwrap();
+        drop(other);
+
+        // Messages sent before the disconnect are still received
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.recv(), Ok(2));
+        assert_eq!(receiver.recv(), Err(RecvError));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)),
+                   Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn test_recv_timeout_max() {
+        let (sender, receiver) = channel::<i32>();
+        drop(sender);
+        assert_eq!(receiver.recv_timeout(Duration::MAX),
+                   Err(RecvTimeoutError::Disconnected));
+
+        let (sender, receiver) = channel();
+        let handle = thread::spawn(move || receiver.recv_timeout(Duration::MAX));
+        thread::sleep(Duration::from_millis(20));
+        sender.send(1).unwrap();
+        drop(sender);
+        assert_eq!(handle.join().unwrap(), Ok(1));
+    }
+
+    #[test]
+    fn test_blocked_recv_wakes_on_disconnect() {
+        let (sender, receiver) = channel::<i32>();
+        let handle = thread::spawn(move || receiver.recv());
+        thread::sleep(Duration::from_millis(20));
+        drop(sender);
+        assert_eq!(handle.join().unwrap(), Err(RecvError));
+    }
+
+    #[test]
+    fn test_send_after_receiver_dropped() {
+        let (sender, receiver) = sync_channel(1);
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(SendError(1)));
+        assert_eq!(sender.try_send(2), Err(TrySendError::Disconnected(2)));
+    }
+
+    #[test]
+    fn test_recv_timeout() {
+        let (sender, receiver) = channel();
+        assert_eq!(receiver.recv_timeout(Duration::from_millis(10)),
+                   Err(RecvTimeoutError::Timeout));
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send(5).unwrap();
+            sender
+        });
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(5));
+        drop(handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_bounded_channel_blocks_senders() {
+        let (sender, receiver) = sync_channel(2);
+        sender.send(1).unwrap();
+        sender.try_send(2).unwrap();
+        assert_eq!(sender.try_send(3), Err(TrySendError::Full(3)));
+
+        // The blocked send completes once the receiver makes room
+        let handle = thread::spawn(move || sender.send(3));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(handle.join().unwrap(), Ok(()));
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_blocked_send_fails_when_receiver_dropped() {
+        let (sender, receiver) = sync_channel(1);
+        sender.send(1).unwrap();
+        let handle = thread::spawn(move || sender.send(2));
+        thread::sleep(Duration::from_millis(20));
+        drop(receiver);
+        assert_eq!(handle.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_many_senders() {
+        const SENDERS: usize = 8;
+        const PER_SENDER: usize = 1000;
+
+        for &bound in &[None, Some(4)] {
+            let (sender, receiver) = match bound {
+                Some(bound) => sync_channel(bound),
+                None => channel(),
+            };
+            let handles: Vec<_> = (0..SENDERS)
+                .map(|s| {
+                    let sender = sender.clone();
+                    thread::spawn(move || {
+                        for i in 0..PER_SENDER {
+                            sender.send((s, i)).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            drop(sender);
+
+            // Each sender's messages arrive in order and the iterator
+            // ends once every sender is done
+            let mut next = vec![0; SENDERS];
+            for (s, i) in &receiver {
+                assert_eq!(i, next[s]);
+                next[s] += 1;
+            }
+            assert_eq!(next, vec![PER_SENDER; SENDERS]);
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        }
+    }
+}
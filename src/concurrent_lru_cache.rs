@@ -0,0 +1,190 @@
+//! A thread safe LRU cache split into independently locked shards.
+//!
+//! Every key is hashed to pick one of several LRUCache shards,
+//! each behind its own Mutex, so threads that touch different
+//! keys rarely wait on the same lock. The trade off is that
+//! recency is only tracked within a shard, so the entry that
+//! gets evicted is the least recently used one of its shard
+//! rather than of the whole cache.
+
+use cache::CapacityError;
+use lru_cache::LRUCache;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, MutexGuard};
+
+/// A LRU cache that can be shared between threads through &self
+pub struct ConcurrentLRUCache<K: Eq + Hash + Copy, V> {
+    capacity: usize,
+    shards: Vec<Mutex<LRUCache<K, V>>>,
+    hash_builder: RandomState,
+}
+
+impl<K, V> ConcurrentLRUCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    /// Create a new cache holding at most capacity items spread
+    /// over the given number of shards
+    ///
+    /// The number of shards is capped at the capacity so that
+    /// every shard can hold at least one item.
+    ///
+    /// Panics if the capacity or the number of shards is zero.
+    pub fn new(capacity: usize, shards: usize) -> ConcurrentLRUCache<K, V> {
+        match ConcurrentLRUCache::try_new(capacity, shards) {
+            Ok(cache) => cache,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Create a new cache like new or return an error if the
+    /// capacity or the number of shards is zero
+    pub fn try_new(capacity: usize, shards: usize)
+        -> Result<ConcurrentLRUCache<K, V>, CapacityError>
+    {
+        // Without shards there is nowhere to store anything,
+        // which is the same as having no capacity
+        if capacity == 0 || shards == 0 {
+            return Err(CapacityError);
+        }
+
+        let shards = shards.min(capacity);
+        // Spread the remainder over the first shards so the
+        // shard capacities add up to exactly the total capacity
+        let base = capacity / shards;
+        let extra = capacity % shards;
+        let shards = (0..shards)
+            .map(|i| {
+                let shard_capacity = if i < extra { base + 1 } else { base };
+                Mutex::new(LRUCache::new(shard_capacity))
+            })
+            .collect();
+
+        Ok(ConcurrentLRUCache {
+            capacity,
+            shards,
+            hash_builder: RandomState::new(),
+        })
+    }
+
+    fn shard(&self, k: &K) -> MutexGuard<'_, LRUCache<K, V>> {
+        let index = (self.hash_builder.hash_one(k) % self.shards.len() as u64) as usize;
+        self.shards[index].lock().unwrap()
+    }
+
+    /// Retrieves and returns the value for the given key
+    pub fn get(&self, k: K) -> Option<V> {
+        self.shard(&k).get(k)
+    }
+
+    /// Sets a key value pair in the cache
+    pub fn set(&self, k: K, v: V) {
+        self.shard(&k).set(k, v);
+    }
+
+    /// The maximum number of items across all shards
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of items across all shards
+    ///
+    /// The shards are locked one at a time, so the result
+    /// can be stale if other threads are writing.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let cache = ConcurrentLRUCache::new(10, 4);
+
+        cache.set(1, "hello");
+        cache.set(2, "world");
+
+        assert_eq!(cache.get(3), None);
+        assert_eq!(cache.get(1), Some("hello"));
+        assert_eq!(cache.get(2), Some("world"));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_shard_capacities() {
+        let cache: ConcurrentLRUCache<i32, i32> = ConcurrentLRUCache::new(10, 4);
+        let capacities: Vec<_> = cache.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().capacity())
+            .collect();
+        assert_eq!(capacities, vec![3, 3, 2, 2]);
+
+        // More shards than capacity gets capped to the capacity
+        let cache: ConcurrentLRUCache<i32, i32> = ConcurrentLRUCache::new(3, 16);
+        assert_eq!(cache.shards.len(), 3);
+    }
+
+    #[test]
+    fn test_zero_capacity_or_shards_is_rejected() {
+        type Cache = ConcurrentLRUCache<i32, i32>;
+        assert_eq!(Cache::try_new(0, 4).err(), Some(CapacityError));
+        assert_eq!(Cache::try_new(10, 0).err(), Some(CapacityError));
+        assert!(Cache::try_new(1, 1).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "cache capacity must be at least one")]
+    fn test_new_panics_on_zero_capacity() {
+        ConcurrentLRUCache::<i32, i32>::new(0, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "cache capacity must be at least one")]
+    fn test_new_panics_on_zero_shards() {
+        ConcurrentLRUCache::<i32, i32>::new(10, 0);
+    }
+
+    #[test]
+    fn test_capacity_never_exceeded() {
+        let cache = Arc::new(ConcurrentLRUCache::new(64, 8));
+
+        let handles: Vec<_> = (0..8u64)
+            .map(|t| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..2000u64 {
+                        let k = (i * 31 + t * 7) % 500;
+                        cache.set(k, k * 2);
+                        if let Some(v) = cache.get((k + 3) % 500) {
+                            assert_eq!(v, ((k + 3) % 500) * 2);
+                        }
+                        assert!(cache.len() <= 64);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(cache.len() <= 64);
+        for shard in &cache.shards {
+            let shard = shard.lock().unwrap();
+            assert!(shard.len() <= shard.capacity());
+        }
+    }
+}
@@ -2,10 +2,19 @@
 //!
 //! It is completely safe because there are not unsafe
 //! blocks but it is less performant than using
-//! TypedArena + UnsafeCell or raw mutable pointers.
+//! an arena + UnsafeCell or raw mutable pointers.
+//!
+//! The one exception is the borrowing iterator. A chain of
+//! RefCell borrows can't be expressed with Ref guards, so it
+//! reads the nodes through RefCell::try_borrow_unguarded. That
+//! is sound because every method that mutably borrows a node
+//! takes &mut self, so no node can be mutably borrowed while
+//! the iterator borrows the deque.
 
+use std::cell::{Ref, RefCell, RefMut};
+use std::marker::PhantomData;
+use std::mem;
 use std::rc::Rc;
-use std::cell::RefCell;
 
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
 
@@ -19,7 +28,7 @@ struct Node<T> {
 impl<T> Node<T> {
     fn new(data: T) -> Rc<RefCell<Node<T>>> {
         Rc::new(RefCell::new(Node {
-            data: data,
+            data,
             next: None,
             prev: None,
         }))
@@ -29,6 +38,7 @@ impl<T> Node<T> {
 pub struct Deque<T> {
     head: Link<T>,
     tail: Link<T>,
+    len: usize,
 }
 
 /// A deque implemented as a doubly linked list
@@ -37,11 +47,13 @@ impl<T> Deque<T> {
         Deque {
             head: None,
             tail: None,
+            len: 0,
         }
     }
 
     pub fn push_front(&mut self, data: T) {
         let new_head = Node::new(data);
+        self.len += 1;
 
         match self.head.take() {
             Some(old_head) => {
@@ -64,6 +76,7 @@ impl<T> Deque<T> {
 
     pub fn push_back(&mut self, data: T) {
         let new_tail = Node::new(data);
+        self.len += 1;
 
         match self.tail.take() {
             Some(old_tail) => {
@@ -86,6 +99,7 @@ impl<T> Deque<T> {
             } else {
                 self.tail.take();
             }
+            self.len -= 1;
 
             // Rc::try_unwrap unwraps a reference counted pointer only if
             // the reference count is 1
@@ -101,10 +115,454 @@ impl<T> Deque<T> {
             } else {
                 self.head.take();
             }
+            self.len -= 1;
 
             Rc::try_unwrap(old_tail).ok().unwrap().into_inner().data
         })
     }
+
+    pub fn peek_front(&self) -> Option<Ref<'_, T>> {
+        self.head.as_ref().map(|node| Ref::map(node.borrow(), |node| &node.data))
+    }
+
+    pub fn peek_back(&self) -> Option<Ref<'_, T>> {
+        self.tail.as_ref().map(|node| Ref::map(node.borrow(), |node| &node.data))
+    }
+
+    pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
+        self.head.as_ref().map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.data))
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<RefMut<'_, T>> {
+        self.tail.as_ref().map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.data))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// Iterates from the front of the deque to the back
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.head.as_deref(),
+            back: self.tail.as_deref(),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns a cursor that starts at the front of the deque
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            current: self.head.clone(),
+            index: 0,
+            deque: self,
+        }
+    }
+
+    /// Returns a cursor that starts at the back of the deque
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            current: self.tail.clone(),
+            index: self.len.saturating_sub(1),
+            deque: self,
+        }
+    }
+
+    /// Moves every element of other to the back of this deque in
+    /// O(1), leaving other empty
+    pub fn append(&mut self, other: &mut Deque<T>) {
+        let (other_head, other_tail, other_len) = match other.take_links() {
+            (Some(head), Some(tail), len) => (head, tail, len),
+            _ => return,
+        };
+
+        match self.tail.take() {
+            Some(old_tail) => {
+                other_head.borrow_mut().prev = Some(old_tail.clone());
+                old_tail.borrow_mut().next = Some(other_head);
+            }
+            None => self.head = Some(other_head),
+        }
+        self.tail = Some(other_tail);
+        self.len += other_len;
+    }
+
+    /// Splits the deque in two at the given index and returns the
+    /// elements from the index on. Walks from whichever end is closer.
+    /// Panics if the index is greater than the length
+    pub fn split_off(&mut self, at: usize) -> Deque<T> {
+        assert!(at <= self.len, "split index out of bounds");
+        if at == 0 {
+            return mem::take(self);
+        }
+
+        let len = self.len;
+        let mut cursor = if at - 1 < len - at {
+            let mut cursor = self.cursor_front_mut();
+            for _ in 0..at - 1 {
+                cursor.move_next();
+            }
+            cursor
+        } else {
+            let mut cursor = self.cursor_back_mut();
+            for _ in at..len {
+                cursor.move_prev();
+            }
+            cursor
+        };
+        cursor.split_after()
+    }
+
+    /// Reverses the order of the elements in place
+    pub fn reverse(&mut self) {
+        let mut link = self.head.clone();
+        while let Some(node) = link {
+            let mut guard = node.borrow_mut();
+            let inner = &mut *guard;
+            mem::swap(&mut inner.next, &mut inner.prev);
+            link = inner.prev.clone();
+        }
+        mem::swap(&mut self.head, &mut self.tail);
+    }
+
+    /// Removes every element for which the predicate returns false
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let mut cursor = self.cursor_front_mut();
+        while let Some(keep) = cursor.current.as_ref().map(|node| f(&node.borrow().data)) {
+            if keep {
+                cursor.move_next();
+            } else {
+                cursor.remove_current();
+            }
+        }
+    }
+
+    /// Takes the nodes out of the deque, leaving it empty
+    fn take_links(&mut self) -> (Link<T>, Link<T>, usize) {
+        let len = mem::replace(&mut self.len, 0);
+        (self.head.take(), self.tail.take(), len)
+    }
+}
+
+impl<T> Default for Deque<T> {
+    fn default() -> Self {
+        Deque::new()
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        // Both links are strong, so the nodes would keep each other
+        // alive. Popping them one at a time breaks the cycles without
+        // recursing through the chain.
+        while self.pop_front().is_some() {}
+    }
+}
+
+/// Iterator over references to the elements of a deque
+pub struct Iter<'a, T: 'a> {
+    front: Option<&'a RefCell<Node<T>>>,
+    back: Option<&'a RefCell<Node<T>>>,
+    len: usize,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iter<'a, T> {
+    /// Reads a node without a borrow guard, which is sound for as
+    /// long as the deque is borrowed (see the module comment)
+    fn node(cell: &'a RefCell<Node<T>>) -> &'a Node<T> {
+        unsafe { cell.try_borrow_unguarded().unwrap() }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.front.map(|cell| {
+            let node = Iter::node(cell);
+            self.front = node.next.as_deref();
+            self.len -= 1;
+            &node.data
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.back.map(|cell| {
+            let node = Iter::node(cell);
+            self.back = node.prev.as_deref();
+            self.len -= 1;
+            &node.data
+        })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a Deque<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator that pops the elements of a deque from either end
+pub struct IntoIter<T>(Deque<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for Deque<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+/// A cursor over a deque that can edit it at the cursor's position.
+///
+/// Past the back and before the front of the deque the cursor points
+/// at a "ghost" position that holds no element, so moving past one
+/// end wraps around to the other end.
+pub struct CursorMut<'a, T: 'a> {
+    deque: &'a mut Deque<T>,
+    /// None when the cursor is at the ghost position
+    current: Link<T>,
+    index: usize,
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    /// Returns the index of the current element, or None at the
+    /// ghost position
+    pub fn index(&self) -> Option<usize> {
+        self.current.as_ref().map(|_| self.index)
+    }
+
+    pub fn current(&mut self) -> Option<RefMut<'_, T>> {
+        self.current.as_ref().map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.data))
+    }
+
+    /// Moves the cursor towards the back of the deque
+    pub fn move_next(&mut self) {
+        match self.current.take() {
+            Some(node) => {
+                self.current = node.borrow().next.clone();
+                self.index += 1;
+            }
+            None => {
+                self.current = self.deque.head.clone();
+                self.index = 0;
+            }
+        }
+    }
+
+    /// Moves the cursor towards the front of the deque
+    pub fn move_prev(&mut self) {
+        match self.current.take() {
+            Some(node) => {
+                self.current = node.borrow().prev.clone();
+                self.index = self.index.checked_sub(1).unwrap_or(self.deque.len);
+            }
+            None => {
+                self.current = self.deque.tail.clone();
+                self.index = self.deque.len.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Inserts the data after the current element, or at the front
+    /// of the deque at the ghost position
+    pub fn insert_after(&mut self, data: T) {
+        let node = match self.current {
+            Some(ref node) => node,
+            None => return self.deque.push_front(data),
+        };
+
+        let new_node = Node::new(data);
+        let next = node.borrow_mut().next.take();
+        match next {
+            Some(ref next) => next.borrow_mut().prev = Some(new_node.clone()),
+            None => self.deque.tail = Some(new_node.clone()),
+        }
+        new_node.borrow_mut().next = next;
+        new_node.borrow_mut().prev = Some(node.clone());
+        node.borrow_mut().next = Some(new_node);
+        self.deque.len += 1;
+    }
+
+    /// Inserts the data before the current element, or at the back
+    /// of the deque at the ghost position
+    pub fn insert_before(&mut self, data: T) {
+        let node = match self.current {
+            Some(ref node) => node,
+            None => return self.deque.push_back(data),
+        };
+
+        let new_node = Node::new(data);
+        let prev = node.borrow_mut().prev.take();
+        match prev {
+            Some(ref prev) => prev.borrow_mut().next = Some(new_node.clone()),
+            None => self.deque.head = Some(new_node.clone()),
+        }
+        new_node.borrow_mut().prev = prev;
+        new_node.borrow_mut().next = Some(node.clone());
+        node.borrow_mut().prev = Some(new_node);
+        self.deque.len += 1;
+        self.index += 1;
+    }
+
+    /// Removes the current element and moves the cursor to the
+    /// element after it
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.current.take()?;
+        let prev = node.borrow_mut().prev.take();
+        let next = node.borrow_mut().next.take();
+        match prev {
+            Some(ref prev) => prev.borrow_mut().next = next.clone(),
+            None => self.deque.head = next.clone(),
+        }
+        match next {
+            Some(ref next) => next.borrow_mut().prev = prev.clone(),
+            None => self.deque.tail = prev.clone(),
+        }
+        self.current = next;
+        self.deque.len -= 1;
+
+        Some(Rc::try_unwrap(node).ok().unwrap().into_inner().data)
+    }
+
+    /// Splits off and returns the elements after the current
+    /// element, or the whole deque at the ghost position
+    pub fn split_after(&mut self) -> Deque<T> {
+        let node = match self.current {
+            Some(ref node) => node,
+            None => return mem::take(self.deque),
+        };
+
+        let next = match node.borrow_mut().next.take() {
+            Some(next) => next,
+            None => return Deque::new(),
+        };
+        next.borrow_mut().prev = None;
+        let len = self.deque.len - self.index - 1;
+        self.deque.len -= len;
+        Deque {
+            head: Some(next),
+            tail: self.deque.tail.replace(node.clone()),
+            len,
+        }
+    }
+
+    /// Splits off and returns the elements before the current
+    /// element, or the whole deque at the ghost position
+    pub fn split_before(&mut self) -> Deque<T> {
+        let node = match self.current {
+            Some(ref node) => node,
+            None => return mem::take(self.deque),
+        };
+
+        let prev = match node.borrow_mut().prev.take() {
+            Some(prev) => prev,
+            None => return Deque::new(),
+        };
+        prev.borrow_mut().next = None;
+        let len = mem::replace(&mut self.index, 0);
+        self.deque.len -= len;
+        Deque {
+            head: self.deque.head.replace(node.clone()),
+            tail: Some(prev),
+            len,
+        }
+    }
+
+    /// Moves the elements of other in after the current element,
+    /// or at the front of the deque at the ghost position
+    pub fn splice_after(&mut self, mut other: Deque<T>) {
+        let (other_head, other_tail, other_len) = match other.take_links() {
+            (Some(head), Some(tail), len) => (head, tail, len),
+            _ => return,
+        };
+
+        let next = match self.current {
+            Some(ref node) => {
+                other_head.borrow_mut().prev = Some(node.clone());
+                node.borrow_mut().next.replace(other_head)
+            }
+            None => self.deque.head.replace(other_head),
+        };
+        match next {
+            Some(next) => {
+                next.borrow_mut().prev = Some(other_tail.clone());
+                other_tail.borrow_mut().next = Some(next);
+            }
+            None => self.deque.tail = Some(other_tail),
+        }
+        self.deque.len += other_len;
+    }
+
+    /// Moves the elements of other in before the current element,
+    /// or at the back of the deque at the ghost position
+    pub fn splice_before(&mut self, mut other: Deque<T>) {
+        let (other_head, other_tail, other_len) = match other.take_links() {
+            (Some(head), Some(tail), len) => (head, tail, len),
+            _ => return,
+        };
+
+        let prev = match self.current {
+            Some(ref node) => {
+                other_tail.borrow_mut().next = Some(node.clone());
+                self.index += other_len;
+                node.borrow_mut().prev.replace(other_tail)
+            }
+            None => self.deque.tail.replace(other_tail),
+        };
+        match prev {
+            Some(prev) => {
+                prev.borrow_mut().next = Some(other_head.clone());
+                other_head.borrow_mut().prev = Some(prev);
+            }
+            None => self.deque.head = Some(other_head),
+        }
+        self.deque.len += other_len;
+    }
 }
 
 #[test]
@@ -125,3 +583,297 @@ fn test_deque() {
     assert_eq!(deque.pop_front(), Some(3));
     assert_eq!(deque.pop_front(), Some(2));
 }
+
+#[test]
+fn test_deque_peek() {
+    let mut deque: Deque<i32> = Deque::new();
+    assert!(deque.peek_front().is_none());
+    assert!(deque.peek_back().is_none());
+
+    deque.push_back(1);
+    deque.push_back(2);
+    deque.push_front(0);
+    assert_eq!(*deque.peek_front().unwrap(), 0);
+    assert_eq!(*deque.peek_back().unwrap(), 2);
+
+    *deque.peek_front_mut().unwrap() = 10;
+    *deque.peek_back_mut().unwrap() *= 10;
+    assert_eq!(deque.len(), 3);
+    assert_eq!(deque.pop_front(), Some(10));
+    assert_eq!(deque.pop_back(), Some(20));
+    assert_eq!(deque.pop_back(), Some(1));
+    assert_eq!(deque.len(), 0);
+    assert!(deque.is_empty());
+}
+
+#[test]
+fn test_deque_iter() {
+    let mut deque: Deque<i32> = Deque::new();
+    for i in 1..5 {
+        deque.push_back(i);
+    }
+
+    assert_eq!(deque.iter().collect::<Vec<_>>(), vec![&1, &2, &3, &4]);
+    assert_eq!(deque.iter().rev().collect::<Vec<_>>(), vec![&4, &3, &2, &1]);
+
+    // The two ends of the iterator stop when they meet
+    let mut iter = deque.iter();
+    assert_eq!(iter.next(), Some(&1));
+    assert_eq!(iter.next_back(), Some(&4));
+    assert_eq!(iter.next(), Some(&2));
+    assert_eq!(iter.next_back(), Some(&3));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+
+    // Shared borrows of the nodes can overlap with the iterator
+    let front = deque.peek_front().unwrap();
+    assert_eq!((&deque).into_iter().len(), 4);
+    assert_eq!(*front, 1);
+}
+
+#[test]
+fn test_deque_into_iter() {
+    let mut deque: Deque<i32> = Deque::new();
+    for i in 1..5 {
+        deque.push_back(i);
+    }
+
+    let mut iter = deque.into_iter();
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next_back(), Some(4));
+    assert_eq!(iter.len(), 2);
+    assert_eq!(iter.collect::<Vec<_>>(), vec![2, 3]);
+}
+
+#[test]
+fn test_deque_drop_releases_elements() {
+    let rc = Rc::new(());
+    {
+        let mut deque = Deque::new();
+        for _ in 0..10 {
+            deque.push_back(rc.clone());
+            deque.push_front(rc.clone());
+        }
+        assert_eq!(Rc::strong_count(&rc), 21);
+    }
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_deque_drop_long() {
+    let mut deque = Deque::new();
+    for i in 0..10_000_000 {
+        deque.push_back(i);
+    }
+    drop(deque);
+}
+
+#[cfg(test)]
+fn deque_from(elems: &[i32]) -> Deque<i32> {
+    let mut deque = Deque::new();
+    for &elem in elems {
+        deque.push_back(elem);
+    }
+    deque
+}
+
+/// Checks the links in both directions against the expected elements
+#[cfg(test)]
+fn assert_deque(deque: &Deque<i32>, expected: &[i32]) {
+    let mut reversed = expected.to_vec();
+    reversed.reverse();
+    assert_eq!(deque.iter().cloned().collect::<Vec<_>>(), expected);
+    assert_eq!(deque.iter().rev().cloned().collect::<Vec<_>>(), reversed);
+    assert_eq!(deque.len(), expected.len());
+}
+
+#[test]
+fn test_cursor_move() {
+    let mut deque = deque_from(&[1, 2, 3]);
+    let mut cursor = deque.cursor_front_mut();
+    assert_eq!(cursor.index(), Some(0));
+    assert_eq!(*cursor.current().unwrap(), 1);
+    cursor.move_next();
+    cursor.move_next();
+    assert_eq!(cursor.index(), Some(2));
+    assert_eq!(*cursor.current().unwrap(), 3);
+
+    // Moving past either end goes through the ghost position
+    cursor.move_next();
+    assert_eq!(cursor.index(), None);
+    assert!(cursor.current().is_none());
+    cursor.move_next();
+    assert_eq!(cursor.index(), Some(0));
+    cursor.move_prev();
+    assert_eq!(cursor.index(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.index(), Some(2));
+    *cursor.current().unwrap() = 30;
+    assert_deque(&deque, &[1, 2, 30]);
+
+    let mut empty: Deque<i32> = Deque::new();
+    let mut cursor = empty.cursor_back_mut();
+    assert_eq!(cursor.index(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.index(), None);
+}
+
+#[test]
+fn test_cursor_insert() {
+    let mut deque = deque_from(&[2, 4]);
+    {
+        let mut cursor = deque.cursor_front_mut();
+        cursor.insert_before(1);
+        assert_eq!(cursor.index(), Some(1));
+        cursor.insert_after(3);
+        cursor.move_next();
+        cursor.move_next();
+        cursor.insert_after(5);
+        assert_eq!(*cursor.current().unwrap(), 4);
+
+        // At the ghost position inserts go to the ends
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.index(), None);
+        cursor.insert_after(0);
+        cursor.insert_before(6);
+    }
+    assert_deque(&deque, &[0, 1, 2, 3, 4, 5, 6]);
+    assert_eq!(deque.pop_back(), Some(6));
+    assert_eq!(deque.pop_front(), Some(0));
+
+    let mut deque = Deque::new();
+    deque.cursor_front_mut().insert_before(1);
+    assert_deque(&deque, &[1]);
+}
+
+#[test]
+fn test_cursor_remove() {
+    let mut deque = deque_from(&[1, 2, 3, 4]);
+    {
+        let mut cursor = deque.cursor_front_mut();
+        assert_eq!(cursor.remove_current(), Some(1));
+        assert_eq!(*cursor.current().unwrap(), 2);
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(3));
+        assert_eq!(cursor.index(), Some(1));
+        assert_eq!(cursor.remove_current(), Some(4));
+        assert_eq!(cursor.index(), None);
+        assert_eq!(cursor.remove_current(), None);
+    }
+    assert_deque(&deque, &[2]);
+
+    deque.cursor_back_mut().remove_current();
+    assert_deque(&deque, &[]);
+    deque.push_back(5);
+    assert_deque(&deque, &[5]);
+}
+
+#[test]
+fn test_cursor_split() {
+    let mut deque = deque_from(&[1, 2, 3, 4, 5]);
+    let (before, after) = {
+        let mut cursor = deque.cursor_front_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let after = cursor.split_after();
+        let before = cursor.split_before();
+        assert_eq!(cursor.index(), Some(0));
+        assert!(cursor.split_after().is_empty());
+        (before, after)
+    };
+    assert_deque(&before, &[1, 2]);
+    assert_deque(&deque, &[3]);
+    assert_deque(&after, &[4, 5]);
+
+    let mut deque = deque_from(&[1, 2]);
+    let mut all = deque.cursor_front_mut();
+    all.move_prev();
+    let all = all.split_before();
+    assert_deque(&all, &[1, 2]);
+    assert_deque(&deque, &[]);
+}
+
+#[test]
+fn test_cursor_splice() {
+    let mut deque = deque_from(&[1, 5]);
+    {
+        let mut cursor = deque.cursor_front_mut();
+        cursor.splice_after(deque_from(&[2, 3]));
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.splice_before(deque_from(&[4]));
+        assert_eq!(cursor.index(), Some(4));
+        assert_eq!(*cursor.current().unwrap(), 5);
+        cursor.splice_before(Deque::new());
+
+        // At the ghost position splices go to the ends
+        cursor.move_next();
+        cursor.splice_after(deque_from(&[-1, 0]));
+        cursor.splice_before(deque_from(&[6, 7]));
+    }
+    assert_deque(&deque, &[-1, 0, 1, 2, 3, 4, 5, 6, 7]);
+
+    let mut empty = Deque::new();
+    empty.cursor_front_mut().splice_after(deque_from(&[1, 2]));
+    assert_deque(&empty, &[1, 2]);
+}
+
+#[test]
+fn test_deque_append() {
+    let mut deque = deque_from(&[1, 2]);
+    let mut other = deque_from(&[3, 4]);
+    deque.append(&mut other);
+    assert_deque(&deque, &[1, 2, 3, 4]);
+    assert_deque(&other, &[]);
+
+    deque.append(&mut other);
+    assert_deque(&deque, &[1, 2, 3, 4]);
+    other.append(&mut deque);
+    assert_deque(&other, &[1, 2, 3, 4]);
+    assert_deque(&deque, &[]);
+}
+
+#[test]
+fn test_deque_split_off() {
+    for at in 0..6 {
+        let mut deque = deque_from(&[0, 1, 2, 3, 4]);
+        let split = deque.split_off(at);
+        let expected: Vec<_> = (0..5).collect();
+        assert_deque(&deque, &expected[..at]);
+        assert_deque(&split, &expected[at..]);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_deque_split_off_out_of_bounds() {
+    deque_from(&[1, 2]).split_off(3);
+}
+
+#[test]
+fn test_deque_reverse() {
+    let mut deque = deque_from(&[1, 2, 3]);
+    deque.reverse();
+    assert_deque(&deque, &[3, 2, 1]);
+    deque.push_front(4);
+    deque.push_back(0);
+    assert_deque(&deque, &[4, 3, 2, 1, 0]);
+
+    let mut empty: Deque<i32> = Deque::new();
+    empty.reverse();
+    assert_deque(&empty, &[]);
+}
+
+#[test]
+fn test_deque_retain() {
+    let mut deque = deque_from(&[1, 2, 3, 4, 5, 6]);
+    deque.retain(|&elem| elem % 2 == 0);
+    assert_deque(&deque, &[2, 4, 6]);
+    deque.retain(|&elem| elem > 4);
+    assert_deque(&deque, &[6]);
+    deque.retain(|_| false);
+    assert_deque(&deque, &[]);
+}
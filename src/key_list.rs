@@ -0,0 +1,65 @@
+//! An ordered list of keys used to track recency in caches.
+//!
+//! Every key is stamped with an increasing tick so the list can
+//! be kept in a BTreeMap and a key can be removed from the middle
+//! without walking the list. It is completely safe but every
+//! operation is O(log n) instead of the O(1) of a linked list.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+pub struct KeyList<K: Eq + Hash + Copy> {
+    ticks: HashMap<K, u64>,
+    order: BTreeMap<u64, K>,
+    next_tick: u64,
+}
+
+impl<K: Eq + Hash + Copy> KeyList<K> {
+    pub fn new() -> Self {
+        KeyList {
+            ticks: HashMap::new(),
+            order: BTreeMap::new(),
+            next_tick: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, k: &K) -> bool {
+        self.ticks.contains_key(k)
+    }
+
+    /// Adds a key as the most recent key in the list
+    pub fn push_front(&mut self, k: K) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        if let Some(old_tick) = self.ticks.insert(k, tick) {
+            self.order.remove(&old_tick);
+        }
+        self.order.insert(tick, k);
+    }
+
+    /// Removes a key from anywhere in the list and
+    /// returns true if it was in the list
+    pub fn remove(&mut self, k: &K) -> bool {
+        match self.ticks.remove(k) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes and returns the least recent key in the list
+    pub fn pop_back(&mut self) -> Option<K> {
+        self.order.pop_first().map(|(_, k)| {
+            self.ticks.remove(&k);
+            k
+        })
+    }
+}
@@ -0,0 +1,177 @@
+//! A least frequently used cache.
+//!
+//! Every entry counts how many times it has been accessed and
+//! the entry with the lowest count is evicted first, with ties
+//! broken by evicting the least recently used entry. The entries
+//! are ordered by (count, tick) in a BTreeMap so finding the
+//! entry to evict is O(log n).
+
+use cache::{Cache, CapacityError};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+struct Entry<V> {
+    val: V,
+    freq: u64,
+    tick: u64,
+}
+
+/// A cache that evicts least frequently used entries
+/// when exceeding given capacity
+pub struct LFUCache<K: Eq + Hash + Copy, V> {
+    capacity: usize,
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<(u64, u64), K>,
+    next_tick: u64,
+}
+
+impl<K, V> LFUCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    /// Create a new LFU cache with the given capacity
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize) -> LFUCache<K, V> {
+        assert!(capacity > 0, "{}", CapacityError);
+
+        LFUCache {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_tick: 0,
+        }
+    }
+
+    /// Create a new LFU cache with the given capacity or
+    /// return an error if the capacity is zero
+    pub fn try_new(capacity: usize) -> Result<LFUCache<K, V>, CapacityError> {
+        if capacity == 0 {
+            Err(CapacityError)
+        } else {
+            Ok(LFUCache::new(capacity))
+        }
+    }
+
+    /// Bumps the access count of the key and returns its entry
+    fn touch(&mut self, k: K) -> Option<&mut Entry<V>> {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        let entry = self.entries.get_mut(&k)?;
+        self.order.remove(&(entry.freq, entry.tick));
+        entry.freq += 1;
+        entry.tick = tick;
+        self.order.insert((entry.freq, entry.tick), k);
+        Some(entry)
+    }
+
+    /// The number of times the key has been accessed
+    pub fn frequency(&self, k: &K) -> Option<u64> {
+        self.entries.get(k).map(|entry| entry.freq)
+    }
+}
+
+impl<K, V> Cache<K, V> for LFUCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    fn get(&mut self, k: K) -> Option<V> {
+        self.touch(k).map(|entry| entry.val.clone())
+    }
+
+    fn set(&mut self, k: K, v: V) {
+        if let Some(entry) = self.touch(k) {
+            entry.val = v;
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            if let Some((_, evicted)) = self.order.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        self.order.insert((1, tick), k);
+        self.entries.insert(k,
+                            Entry {
+                                val: v,
+                                freq: 1,
+                                tick,
+                            });
+    }
+
+    fn contains(&self, k: &K) -> bool {
+        self.entries.contains_key(k)
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cache::Cache;
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_frequent() {
+        let mut cache = LFUCache::new(3);
+        cache.set(1, "1");
+        cache.set(2, "2");
+        cache.set(3, "3");
+
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.get(3), Some("3"));
+
+        // 2 has only been set once
+        cache.set(4, "4");
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.get(3), Some("3"));
+        assert_eq!(cache.get(4), Some("4"));
+    }
+
+    #[test]
+    fn test_ties_evict_least_recent() {
+        let mut cache = LFUCache::new(2);
+        cache.set(1, "1");
+        cache.set(2, "2");
+
+        cache.set(3, "3");
+        assert!(!cache.contains(&1));
+        assert!(cache.contains(&2));
+    }
+
+    #[test]
+    fn test_set_counts_as_access() {
+        let mut cache = LFUCache::new(2);
+        cache.set(1, "1");
+        cache.set(1, "one");
+        assert_eq!(cache.frequency(&1), Some(2));
+
+        cache.set(2, "2");
+        cache.set(3, "3");
+        assert_eq!(cache.get(1), Some("one"));
+        assert_eq!(cache.get(2), None);
+    }
+
+    #[test]
+    fn test_zero_capacity_is_rejected() {
+        assert_eq!(LFUCache::<i32, i32>::try_new(0).err(), Some(CapacityError));
+        assert!(LFUCache::<i32, i32>::try_new(1).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "cache capacity must be at least one")]
+    fn test_new_panics_on_zero_capacity() {
+        LFUCache::<i32, i32>::new(0);
+    }
+}
@@ -1,19 +1,48 @@
 //! Reimplementations of common data structures in Rust
 
-#![feature(rustc_private)]
-
-extern crate arena;
+extern crate typed_arena;
+#[cfg(loom)]
+extern crate loom;
 
 pub mod arena_deque;
 pub mod arena_graph;
+pub mod cache;
+pub mod lfu_cache;
+pub mod arc_cache;
+pub mod two_queue_cache;
 pub mod lru_cache;
+pub mod concurrent_lru_cache;
+pub mod loading_cache;
+pub mod stored_cache;
+pub mod persist;
 pub mod stack;
+pub mod persistent_stack;
 pub mod deque;
 pub mod queue;
+pub mod real_time_queue;
+pub mod ring_buffer;
+pub mod spsc_queue;
+pub mod mpmc_queue;
+pub mod channel;
 pub mod unsafe_queue;
 
+mod key_list;
+mod sync;
+
+pub use arc_cache::ARCCache;
+pub use cache::{Cache, CapacityError};
+pub use concurrent_lru_cache::ConcurrentLRUCache;
 pub use deque::Deque;
+pub use lfu_cache::LFUCache;
+pub use loading_cache::LoadingCache;
 pub use lru_cache::LRUCache;
+pub use mpmc_queue::MpmcQueue;
+pub use persistent_stack::{ArcPersistentStack, PersistentStack};
 pub use queue::Queue;
+pub use real_time_queue::RealTimeQueue;
+pub use ring_buffer::RingBuffer;
 pub use stack::Stack;
+pub use stored_cache::StoredCache;
+pub use two_queue_cache::TwoQueueCache;
+pub use typed_arena::Arena;
 pub use unsafe_queue::List;
@@ -0,0 +1,392 @@
+//! A thread safe cache that loads missing values itself.
+//!
+//! Callers ask for a key and the cache runs a loader function
+//! when the key is missing. If other threads miss on the same
+//! key while a load is running, they wait on a Condvar for the
+//! result of that load instead of starting their own, so each
+//! missing key is only loaded once no matter how many threads
+//! want it. The loader runs without holding the cache's lock.
+//!
+//! Invalidating a key that is being loaded unregisters the load.
+//! The load still hands its result to the callers waiting on it,
+//! but it only caches the result if it is still the registered
+//! load for its key once it finishes, so a value fetched before
+//! the invalidation is never cached after it.
+
+use lru_cache::{Entry, LRUCache};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// The progress of a single load that other callers can wait on
+enum LoadState<V, E> {
+    Loading,
+    Done(Result<V, E>),
+    /// The loader panicked, so waiters have to try again
+    Abandoned,
+}
+
+struct Load<V, E> {
+    state: Mutex<LoadState<V, E>>,
+    done: Condvar,
+}
+
+struct Inner<K: Eq + Hash + Copy, V, E> {
+    cache: LRUCache<K, Result<V, E>>,
+    loading: HashMap<K, Arc<Load<V, E>>>,
+}
+
+impl<K, V, E> Inner<K, V, E>
+    where K: Eq + Hash + Copy
+{
+    /// Checks if the load is still the registered load for the key,
+    /// which it stops being once the key is invalidated
+    fn is_current(&self, k: &K, load: &Arc<Load<V, E>>) -> bool {
+        match self.loading.get(k) {
+            Some(current) => Arc::ptr_eq(current, load),
+            None => false,
+        }
+    }
+}
+
+/// A LRU cache that fills itself by calling a loader on misses
+pub struct LoadingCache<K: Eq + Hash + Copy, V, E, F> {
+    inner: Mutex<Inner<K, V, E>>,
+    loader: F,
+    /// How long failed loads are cached for, if at all
+    error_ttl: Option<Duration>,
+}
+
+/// Finishes a load when the loading thread is done with it,
+/// even if the loader panicked
+struct LoadGuard<'a, K: 'a + Eq + Hash + Copy, V: 'a, E: 'a> {
+    inner: &'a Mutex<Inner<K, V, E>>,
+    load: Arc<Load<V, E>>,
+    key: K,
+    result: Option<Result<V, E>>,
+}
+
+impl<'a, K, V, E> Drop for LoadGuard<'a, K, V, E>
+    where K: Eq + Hash + Copy
+{
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            if inner.is_current(&self.key, &self.load) {
+                inner.loading.remove(&self.key);
+            }
+        }
+
+        if let Ok(mut state) = self.load.state.lock() {
+            *state = match self.result.take() {
+                Some(result) => LoadState::Done(result),
+                None => LoadState::Abandoned,
+            };
+        }
+        self.load.done.notify_all();
+    }
+}
+
+impl<K, V, E, F> LoadingCache<K, V, E, F>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          E: Clone,
+          F: Fn(&K) -> Result<V, E>
+{
+    /// Create a new loading cache with the given capacity
+    /// that never caches failed loads
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize, loader: F) -> LoadingCache<K, V, E, F> {
+        LoadingCache {
+            inner: Mutex::new(Inner {
+                cache: LRUCache::new(capacity),
+                loading: HashMap::new(),
+            }),
+            loader,
+            error_ttl: None,
+        }
+    }
+
+    /// Create a new loading cache with the given capacity that
+    /// caches failed loads for the given time to live, so a key
+    /// that keeps failing is not loaded on every call
+    ///
+    /// Panics if the capacity is zero.
+    pub fn with_negative_caching(capacity: usize,
+                                 error_ttl: Duration,
+                                 loader: F)
+                                 -> LoadingCache<K, V, E, F> {
+        let mut cache = LoadingCache::new(capacity, loader);
+        cache.error_ttl = Some(error_ttl);
+        cache
+    }
+
+    /// Returns the value for the given key, loading it if it is missing
+    ///
+    /// If another thread is already loading the key, this waits
+    /// for that load and returns its result.
+    pub fn get(&self, k: K) -> Result<V, E> {
+        loop {
+            let load = {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(result) = inner.cache.get(k) {
+                    return result;
+                }
+
+                match inner.loading.get(&k) {
+                    Some(load) => load.clone(),
+                    None => {
+                        let load = Arc::new(Load {
+                            state: Mutex::new(LoadState::Loading),
+                            done: Condvar::new(),
+                        });
+                        inner.loading.insert(k, load.clone());
+                        drop(inner);
+                        return self.load(k, load);
+                    }
+                }
+            };
+
+            let mut state = load.state.lock().unwrap();
+            while let LoadState::Loading = *state {
+                state = load.done.wait(state).unwrap();
+            }
+            if let LoadState::Done(ref result) = *state {
+                return result.clone();
+            }
+            // The loader panicked, so try again
+        }
+    }
+
+    /// Runs the loader for a key this thread is responsible for
+    fn load(&self, k: K, load: Arc<Load<V, E>>) -> Result<V, E> {
+        let mut guard = LoadGuard {
+            inner: &self.inner,
+            load,
+            key: k,
+            result: None,
+        };
+
+        let result = (self.loader)(&k);
+        {
+            // A load invalidated while it ran only goes to its waiters
+            let mut inner = self.inner.lock().unwrap();
+            if inner.is_current(&k, &guard.load) {
+                match (&result, self.error_ttl) {
+                    (&Ok(_), _) => inner.cache.set(k, result.clone()),
+                    (&Err(_), Some(ttl)) => inner.cache.set_with_ttl(k, result.clone(), ttl),
+                    (&Err(_), None) => {}
+                }
+            }
+        }
+
+        guard.result = Some(result.clone());
+        result
+    }
+
+    /// Removes the key so that the next get loads it again, even
+    /// if a load of the key is already running
+    pub fn invalidate(&self, k: K) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.loading.remove(&k);
+        if let Entry::Occupied(entry) = inner.cache.entry(k) {
+            drop(entry.remove());
+        }
+    }
+
+    /// The number of loaded values and cached errors
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use super::*;
+
+    /// A loader that counts its calls and blocks until it is opened
+    struct FakeLoader {
+        calls: AtomicUsize,
+        open: Mutex<bool>,
+        opened: Condvar,
+    }
+
+    impl FakeLoader {
+        fn new(open: bool) -> Arc<FakeLoader> {
+            Arc::new(FakeLoader {
+                calls: AtomicUsize::new(0),
+                open: Mutex::new(open),
+                opened: Condvar::new(),
+            })
+        }
+
+        fn load(&self, k: &i32) -> Result<i32, String> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut open = self.open.lock().unwrap();
+            while !*open {
+                open = self.opened.wait(open).unwrap();
+            }
+
+            // Negative keys fail on their first load only
+            if *k < 0 && call == 0 {
+                Err(format!("failed to load {}", k))
+            } else {
+                Ok(k * 10)
+            }
+        }
+
+        fn open(&self) {
+            *self.open.lock().unwrap() = true;
+            self.opened.notify_all();
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_loads_once_and_caches() {
+        let loader = FakeLoader::new(true);
+        let fake = loader.clone();
+        let cache = LoadingCache::new(2, move |k| fake.load(k));
+
+        assert_eq!(cache.get(1), Ok(10));
+        assert_eq!(cache.get(1), Ok(10));
+        assert_eq!(loader.calls(), 1);
+
+        cache.invalidate(1);
+        assert_eq!(cache.get(1), Ok(10));
+        assert_eq!(loader.calls(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_misses_share_one_load() {
+        let loader = FakeLoader::new(false);
+        let fake = loader.clone();
+        let cache = Arc::new(LoadingCache::new(4, move |k| fake.load(k)));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                thread::spawn(move || cache.get(7))
+            })
+            .collect();
+
+        // Wait until the loading thread and the seven waiting threads
+        // all hold the load, on top of the loading map's reference
+        loop {
+            let waiting = cache.inner
+                .lock()
+                .unwrap()
+                .loading
+                .get(&7)
+                .map_or(0, Arc::strong_count);
+            if waiting == 9 {
+                break;
+            }
+            thread::yield_now();
+        }
+        loader.open();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Ok(70));
+        }
+        assert_eq!(loader.calls(), 1);
+        assert!(cache.inner.lock().unwrap().loading.is_empty());
+    }
+
+    #[test]
+    fn test_invalidate_during_load() {
+        let loader = FakeLoader::new(false);
+        let fake = loader.clone();
+        let cache = Arc::new(LoadingCache::new(2, move |k| fake.load(k)));
+
+        let loading = cache.clone();
+        let handle = thread::spawn(move || loading.get(1));
+        while loader.calls() == 0 {
+            thread::yield_now();
+        }
+
+        cache.invalidate(1);
+        loader.open();
+
+        // The caller still gets the result, but it isn't cached
+        assert_eq!(handle.join().unwrap(), Ok(10));
+        assert!(cache.is_empty());
+        assert!(cache.inner.lock().unwrap().loading.is_empty());
+
+        assert_eq!(cache.get(1), Ok(10));
+        assert_eq!(loader.calls(), 2);
+    }
+
+    #[test]
+    fn test_failed_loads_are_not_cached() {
+        let loader = FakeLoader::new(true);
+        let fake = loader.clone();
+        let cache = LoadingCache::new(2, move |k| fake.load(k));
+
+        assert_eq!(cache.get(-1), Err(String::from("failed to load -1")));
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(-1), Ok(-10));
+        assert_eq!(loader.calls(), 2);
+    }
+
+    #[test]
+    fn test_negative_caching() {
+        let loader = FakeLoader::new(true);
+        let fake = loader.clone();
+        let cache = LoadingCache::with_negative_caching(2,
+                                                        Duration::from_secs(3600),
+                                                        move |k| fake.load(k));
+
+        assert_eq!(cache.get(-1), Err(String::from("failed to load -1")));
+        assert_eq!(cache.get(-1), Err(String::from("failed to load -1")));
+        assert_eq!(loader.calls(), 1);
+
+        cache.invalidate(-1);
+        assert_eq!(cache.get(-1), Ok(-10));
+    }
+
+    #[test]
+    fn test_negative_caching_with_huge_ttl() {
+        let loader = FakeLoader::new(true);
+        let fake = loader.clone();
+        let cache = LoadingCache::with_negative_caching(2, Duration::MAX, move |k| fake.load(k));
+
+        // The error is cached for good without poisoning the lock
+        assert_eq!(cache.get(-1), Err(String::from("failed to load -1")));
+        assert_eq!(cache.get(-1), Err(String::from("failed to load -1")));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(loader.calls(), 1);
+
+        cache.invalidate(-1);
+        assert_eq!(cache.get(-1), Ok(-10));
+        assert_eq!(cache.get(2), Ok(20));
+    }
+
+    #[test]
+    fn test_panicking_loader_is_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let loader_calls = calls.clone();
+        let cache = Arc::new(LoadingCache::new(2, move |k: &i32| -> Result<i32, ()> {
+            if loader_calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first load fails");
+            }
+            Ok(*k)
+        }));
+
+        let panicking = cache.clone();
+        assert!(thread::spawn(move || panicking.get(1)).join().is_err());
+
+        assert_eq!(cache.get(1), Ok(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,41 +1,201 @@
-//! A LRU cache implementation using raw pointers.
+//! A LRU cache that keeps its nodes in a slab.
 //!
-//! It is more performant than reference counting
-//! but more unsafe because of the unsafe blocks
-//! and the pointer manipulation.
+//! The nodes live in one Vec and link to each other by index
+//! instead of each being boxed and linked with raw pointers.
+//! The slot of a node that is evicted, expires or is removed
+//! goes onto a free list that the next new entry takes, so once
+//! the cache has filled up, setting new keys never touches the
+//! allocator.
 
+use cache::Cache;
+pub use cache::CapacityError;
+use persist::{Decode, Encode, PersistError};
 use std::collections::HashMap;
+use std::fs::File;
 use std::hash::Hash;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
 use std::mem;
-use std::ptr;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-/// A key-value node for a doubly linked list
+/// The first bytes of a saved cache
+const MAGIC: &[u8; 4] = b"LRUC";
+/// The version of the saved cache format
+const VERSION: u8 = 1;
+/// The index used for a missing link
+const NIL: usize = usize::MAX;
+
+/// A source of the current time used to expire cache entries
+///
+/// The cache never reads the time on its own so tests can plug in
+/// a clock that they advance by hand.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// A clock backed by the system's monotonic clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Receives every cache event so that counting them
+/// can be compiled out by picking NoStats
+pub trait StatsRecorder: Default {
+    fn record_hit(&mut self);
+    fn record_miss(&mut self);
+    fn record_insertion(&mut self);
+    fn record_update(&mut self);
+    fn record_eviction(&mut self);
+}
+
+/// A stats recorder that ignores every event
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoStats;
+
+impl StatsRecorder for NoStats {
+    #[inline]
+    fn record_hit(&mut self) {}
+    #[inline]
+    fn record_miss(&mut self) {}
+    #[inline]
+    fn record_insertion(&mut self) {}
+    #[inline]
+    fn record_update(&mut self) {}
+    #[inline]
+    fn record_eviction(&mut self) {}
+}
+
+/// Counters for the events of a cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Gets that found a live entry
+    pub hits: u64,
+    /// Gets that found nothing or an expired entry
+    pub misses: u64,
+    /// Sets of keys that were not in the cache
+    pub insertions: u64,
+    /// Sets of keys that were already in the cache
+    pub updates: u64,
+    /// Entries removed to make room or because they expired
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// The fraction of gets that were hits, or 0 if there were no gets
+    pub fn hit_rate(&self) -> f64 {
+        let gets = self.hits + self.misses;
+        if gets == 0 {
+            0.0
+        } else {
+            self.hits as f64 / gets as f64
+        }
+    }
+}
+
+impl StatsRecorder for CacheStats {
+    #[inline]
+    fn record_hit(&mut self) {
+        self.hits += 1;
+    }
+
+    #[inline]
+    fn record_miss(&mut self) {
+        self.misses += 1;
+    }
+
+    #[inline]
+    fn record_insertion(&mut self) {
+        self.insertions += 1;
+    }
+
+    #[inline]
+    fn record_update(&mut self) {
+        self.updates += 1;
+    }
+
+    #[inline]
+    fn record_eviction(&mut self) {
+        self.evictions += 1;
+    }
+}
+
+/// A key-value node for a doubly linked list of slab indexes
 struct Node<K, V> {
     key: K,
     val: V,
-    next: *mut Node<K, V>,
-    prev: *mut Node<K, V>,
+    expires: Option<Instant>,
+    next: usize,
+    prev: usize,
 }
 
 impl<K, V> Node<K, V> {
-    pub fn new(key: K, val: V) -> Node<K, V> {
+    pub fn new(key: K, val: V, expires: Option<Instant>) -> Node<K, V> {
         Node {
-            key: key,
-            val: val,
-            next: ptr::null_mut(),
-            prev: ptr::null_mut(),
+            key,
+            val,
+            expires,
+            next: NIL,
+            prev: NIL,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        match self.expires {
+            Some(expires) => expires <= now,
+            None => false,
+        }
+    }
+}
+
+/// A slot of the slab, which either holds a node or
+/// links to the next vacant slot in the free list
+enum Slot<K, V> {
+    Occupied(Node<K, V>),
+    Vacant(usize),
+}
+
+impl<K, V> Slot<K, V> {
+    fn node(&self) -> &Node<K, V> {
+        match *self {
+            Slot::Occupied(ref node) => node,
+            Slot::Vacant(_) => panic!("slot is vacant"),
+        }
+    }
+
+    fn node_mut(&mut self) -> &mut Node<K, V> {
+        match *self {
+            Slot::Occupied(ref mut node) => node,
+            Slot::Vacant(_) => panic!("slot is vacant"),
         }
     }
 }
 
 /// A cache that evicts least recently used nodes
 /// when exceeding given capacity
-pub struct LRUCache<K: Eq + Hash + Copy, V> {
-    pub capacity: i32,
-    pub count: i32,
-    page_map: HashMap<K, *mut Node<K, V>>,
-    front: *mut Node<K, V>,
-    back: *mut Node<K, V>,
+///
+/// Entries can also be given a time to live, after which
+/// they are treated as missing and removed lazily.
+///
+/// Cache events are only counted when the cache is built
+/// with CacheStats as its stats recorder.
+pub struct LRUCache<K: Eq + Hash + Copy, V, C: Clock = SystemClock, S: StatsRecorder = NoStats> {
+    capacity: usize,
+    count: usize,
+    page_map: HashMap<K, usize>,
+    slots: Vec<Slot<K, V>>,
+    /// The first vacant slot, or NIL if every slot is occupied
+    free: usize,
+    front: usize,
+    back: usize,
+    ttl: Option<Duration>,
+    clock: C,
+    stats: S,
 }
 
 impl<K, V> LRUCache<K, V>
@@ -44,120 +204,820 @@ impl<K, V> LRUCache<K, V>
 {
     /// Create a new LRU cache with the given capacity (the maximum number
     /// of items before evicting the least recently used item)
-    pub fn new(capacity: i32) -> LRUCache<K, V> {
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize) -> LRUCache<K, V> {
+        LRUCache::with_clock(capacity, None, SystemClock)
+    }
+
+    /// Create a new LRU cache with the given capacity or
+    /// return an error if the capacity is zero
+    pub fn try_new(capacity: usize) -> Result<LRUCache<K, V>, CapacityError> {
+        if capacity == 0 {
+            Err(CapacityError)
+        } else {
+            Ok(LRUCache::new(capacity))
+        }
+    }
+
+    /// Create a new LRU cache where every entry expires after the given
+    /// time to live unless it is set with its own time to live
+    pub fn with_ttl(capacity: usize, ttl: Duration) -> LRUCache<K, V> {
+        LRUCache::with_clock(capacity, Some(ttl), SystemClock)
+    }
+}
+
+impl<K, V> LRUCache<K, V, SystemClock, CacheStats>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    /// Create a new LRU cache that counts its hits, misses,
+    /// insertions, updates and evictions
+    pub fn with_stats(capacity: usize) -> LRUCache<K, V, SystemClock, CacheStats> {
+        LRUCache::with_clock_and_stats(capacity, None, SystemClock, CacheStats::default())
+    }
+}
+
+impl<K, V, C> LRUCache<K, V, C>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          C: Clock
+{
+    /// Create a new LRU cache with an optional default time to live
+    /// that reads the current time from the given clock
+    pub fn with_clock(capacity: usize, ttl: Option<Duration>, clock: C) -> LRUCache<K, V, C> {
+        LRUCache::with_clock_and_stats(capacity, ttl, clock, NoStats)
+    }
+}
+
+impl<K, V, C> LRUCache<K, V, C, CacheStats>
+    where K: Eq + Hash + Copy,
+          C: Clock
+{
+    /// Returns a snapshot of the cache's counters
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Sets every counter back to zero
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+}
+
+impl<K, V, C, S> LRUCache<K, V, C, S>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          C: Clock,
+          S: StatsRecorder
+{
+    /// Create a new LRU cache with an optional default time to live,
+    /// a clock and a recorder for the cache's events
+    ///
+    /// Panics if the capacity is zero, since there would be
+    /// no room for the entry handed out by entry().
+    pub fn with_clock_and_stats(capacity: usize,
+                                ttl: Option<Duration>,
+                                clock: C,
+                                stats: S)
+                                -> LRUCache<K, V, C, S> {
+        assert!(capacity > 0, "{}", CapacityError);
+
         LRUCache {
-            capacity: capacity,
+            capacity,
             count: 0,
             page_map: HashMap::new(),
-            front: ptr::null_mut(),
-            back: ptr::null_mut(),
+            slots: Vec::new(),
+            free: NIL,
+            front: NIL,
+            back: NIL,
+            ttl,
+            clock,
+            stats,
         }
     }
 
-    fn remove(&mut self, n: *mut Node<K, V>) {
-        unsafe {
-            if (*n).prev.is_null() {
-                self.back = (*n).next;
-            } else {
-                (*(*n).prev).next = (*n).next;
-            }
+    /// The maximum number of items before evicting
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
 
-            if (*n).next.is_null() {
-                self.front = (*n).prev;
-            } else {
-                (*(*n).next).prev = (*n).prev;
-            }
+    /// The number of items in the cache, including
+    /// expired items that have not been removed yet
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn remove(&mut self, n: usize) {
+        let (prev, next) = {
+            let node = self.slots[n].node();
+            (node.prev, node.next)
+        };
+
+        if prev == NIL {
+            self.back = next;
+        } else {
+            self.slots[prev].node_mut().next = next;
+        }
+
+        if next == NIL {
+            self.front = prev;
+        } else {
+            self.slots[next].node_mut().prev = prev;
         }
     }
 
-    fn add_to_front(&mut self, n: *mut Node<K, V>) {
-        unsafe {
-            (*n).next = ptr::null_mut();
-            (*n).prev = self.front;
+    fn add_to_front(&mut self, n: usize) {
+        let front = self.front;
+        {
+            let node = self.slots[n].node_mut();
+            node.next = NIL;
+            node.prev = front;
+        }
 
-            if self.back.is_null() {
-                self.back = n;
-            } else {
-                (*self.front).next = n;
-            }
+        if self.back == NIL {
+            self.back = n;
+        } else {
+            self.slots[front].node_mut().next = n;
+        }
+
+        self.front = n;
+    }
+
+    fn move_to_front(&mut self, n: usize) {
+        if n != self.front {
+            self.remove(n);
+            self.add_to_front(n);
+        }
+    }
+
+    /// Unlinks the node, removes its key, puts its slot on
+    /// the free list and returns its value
+    fn take(&mut self, n: usize) -> V {
+        self.remove(n);
+        self.count -= 1;
+
+        let node = match mem::replace(&mut self.slots[n], Slot::Vacant(self.free)) {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => panic!("slot is vacant"),
+        };
+        self.free = n;
+        self.page_map.remove(&node.key);
+        node.val
+    }
+
+    /// Checks if the node has expired, only reading the clock
+    /// when the node has a time to live
+    fn has_expired(&self, n: usize) -> bool {
+        match self.slots[n].node().expires {
+            Some(expires) => expires <= self.clock.now(),
+            None => false,
+        }
+    }
+
+    fn evict(&mut self, n: usize) {
+        self.take(n);
+        self.stats.record_eviction();
+    }
+
+    /// Returns the least recently used entry, which is the next
+    /// one to be evicted, without counting it as an access
+    pub fn peek_lru(&self) -> Option<(K, &V)> {
+        if self.back == NIL {
+            None
+        } else {
+            let node = self.slots[self.back].node();
+            Some((node.key, &node.val))
+        }
+    }
 
-            self.front = n;
+    /// Iterates over the entries from most to least recently used
+    /// without counting them as accesses
+    ///
+    /// Expired entries that have not been removed yet are included.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            slots: &self.slots,
+            front: self.front,
+            back: self.back,
+            len: self.count,
+        }
+    }
+
+    /// Iterates over the entries from most to least recently used
+    /// with mutable references to the values
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            slots: self.slots.as_mut_ptr(),
+            front: self.front,
+            back: self.back,
+            len: self.count,
+            marker: PhantomData,
         }
     }
 
     /// Retrieves and returns the value for the given key
+    ///
+    /// An entry whose time to live has passed is removed
+    /// and reported as missing.
     pub fn get(&mut self, k: K) -> Option<V> {
-        if let Some(node) = self.page_map.remove(&k) {
-            if node != self.front {
-                self.remove(node);
-                self.add_to_front(node);
+        let n = match self.page_map.get(&k) {
+            Some(&n) => n,
+            None => {
+                self.stats.record_miss();
+                return None;
             }
-            self.page_map.insert(k, node);
-            Some(unsafe { (*node).val.clone() })
-        } else {
-            None
+        };
+
+        if self.has_expired(n) {
+            self.evict(n);
+            self.stats.record_miss();
+            return None;
         }
+
+        self.stats.record_hit();
+        self.move_to_front(n);
+        Some(self.slots[n].node().val.clone())
+    }
+
+    /// Gets the given key's entry in the cache for in-place manipulation
+    ///
+    /// Looking up the entry counts as an access, so an existing
+    /// entry becomes the most recently used one.
+    pub fn entry(&mut self, k: K) -> Entry<'_, K, V, C, S> {
+        let node = match self.page_map.get(&k) {
+            Some(&node) => node,
+            None => {
+                self.stats.record_miss();
+                return Entry::Vacant(VacantEntry {
+                    cache: self,
+                    key: k,
+                });
+            }
+        };
+
+        if self.has_expired(node) {
+            self.evict(node);
+            self.stats.record_miss();
+            return Entry::Vacant(VacantEntry {
+                cache: self,
+                key: k,
+            });
+        }
+
+        self.stats.record_hit();
+        self.move_to_front(node);
+        Entry::Occupied(OccupiedEntry {
+            cache: self,
+            node,
+        })
+    }
+
+    /// Returns the value for the given key, first setting it
+    /// to the result of the function if it is missing
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> V {
+        self.entry(k).or_insert_with(f).clone()
     }
 
     /// Sets a key value pair in the cache
+    ///
+    /// The entry expires after the cache's default time to live, if any.
     pub fn set(&mut self, k: K, v: V) {
-        // Create the new front node
-        let new_node = Box::new(Node::new(k, v));
-        // For some reason let ptr: *mut _ = &mut *new_node doesn't
-        // create a different pointer so we have to use mem::transmute.
-        let new_node_ptr = unsafe { mem::transmute::<Box<Node<K, V>>, *mut Node<K, V>>(new_node) };
-
-        if let Some(node) = self.page_map.remove(&k) {
-            self.remove(node);
-            unsafe {
-                mem::transmute::<*mut Node<K, V>, Box<Node<K, V>>>(node);
+        let expires = self.default_expiry();
+        self.insert(k, v, expires);
+    }
+
+    /// Sets a key value pair in the cache that expires after
+    /// the given time to live instead of the cache's default
+    ///
+    /// A time to live too long to add to the current time never expires.
+    pub fn set_with_ttl(&mut self, k: K, v: V, ttl: Duration) {
+        let expires = self.clock.now().checked_add(ttl);
+        self.insert(k, v, expires);
+    }
+
+    /// Removes every expired entry and returns how many were removed
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let mut purged = 0;
+
+        // Walk from the least recently used node to the front
+        let mut n = self.back;
+        while n != NIL {
+            let (next, expired) = {
+                let node = self.slots[n].node();
+                (node.next, node.is_expired(now))
+            };
+            if expired {
+                self.evict(n);
+                purged += 1;
+            }
+            n = next;
+        }
+
+        purged
+    }
+
+    /// When an entry set now expires, which is never if there is
+    /// no default time to live or it is too long to add to now
+    fn default_expiry(&self) -> Option<Instant> {
+        self.ttl.and_then(|ttl| self.clock.now().checked_add(ttl))
+    }
+
+    fn insert(&mut self, k: K, v: V, expires: Option<Instant>) {
+        if let Some(&n) = self.page_map.get(&k) {
+            // Reuse the existing node instead of allocating a new one
+            {
+                let node = self.slots[n].node_mut();
+                node.val = v;
+                node.expires = expires;
             }
-            self.page_map.insert(k, new_node_ptr);
-            self.add_to_front(new_node_ptr);
+            self.move_to_front(n);
+            self.stats.record_update();
         } else {
-            if self.count == self.capacity {
-                let back = self.back;
-                unsafe {
-                    self.page_map.remove(&(*back).key);
-                }
+            self.insert_new(k, v, expires);
+        }
+    }
 
-                self.remove(back);
-                unsafe {
-                    mem::transmute::<*mut Node<K, V>, Box<Node<K, V>>>(back);
-                }
-                self.count -= 1;
+    /// Adds a node for a key that is not in the cache to the front
+    /// and returns its slot
+    fn insert_new(&mut self, k: K, v: V, expires: Option<Instant>) -> usize {
+        if self.count == self.capacity {
+            let back = self.back;
+            self.evict(back);
+        }
+
+        // Take a vacant slot, and only grow the slab when there is
+        // none, which can only happen before the cache first fills up
+        let slot = Slot::Occupied(Node::new(k, v, expires));
+        let n = if self.free == NIL {
+            self.slots.push(slot);
+            self.slots.len() - 1
+        } else {
+            let n = self.free;
+            self.free = match mem::replace(&mut self.slots[n], slot) {
+                Slot::Vacant(next) => next,
+                Slot::Occupied(_) => panic!("free slot is occupied"),
+            };
+            n
+        };
+
+        self.add_to_front(n);
+        self.page_map.insert(k, n);
+        self.count += 1;
+        self.stats.record_insertion();
+        n
+    }
+}
+
+impl<K, V> LRUCache<K, V>
+    where K: Eq + Hash + Copy + Decode,
+          V: Clone + Decode
+{
+    /// Create a new LRU cache with the given capacity from entries
+    /// written by save, keeping the most recently used entries
+    /// if there are more entries than the capacity
+    ///
+    /// Panics if the capacity is zero.
+    pub fn load<R: Read>(reader: R, capacity: usize) -> Result<LRUCache<K, V>, PersistError> {
+        let mut cache = LRUCache::new(capacity);
+        cache.restore(reader)?;
+        Ok(cache)
+    }
+
+    /// Create a new LRU cache from a file written by save_to_file
+    pub fn load_from_file<P: AsRef<Path>>(path: P,
+                                          capacity: usize)
+                                          -> Result<LRUCache<K, V>, PersistError> {
+        LRUCache::load(BufReader::new(File::open(path)?), capacity)
+    }
+}
+
+impl<K, V, C, S> LRUCache<K, V, C, S>
+    where K: Eq + Hash + Copy + Encode,
+          V: Clone + Encode,
+          C: Clock,
+          S: StatsRecorder
+{
+    /// Writes the live entries from least to most recently used,
+    /// along with the time each one has left to live
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), PersistError> {
+        let now = self.clock.now();
+        let mut entries = Vec::new();
+        let mut count = 0u64;
+
+        let mut n = self.back;
+        while n != NIL {
+            let node = self.slots[n].node();
+            if !node.is_expired(now) {
+                node.key.encode(&mut entries);
+                node.val.encode(&mut entries);
+                node.expires.map(|expires| expires - now).encode(&mut entries);
+                count += 1;
             }
+            n = node.next;
+        }
+
+        let mut out = Vec::with_capacity(MAGIC.len() + 9 + entries.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        count.encode(&mut out);
+        out.extend_from_slice(&entries);
+
+        writer.write_all(&out)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the live entries to a file, replacing it if it exists
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistError> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<K, V, C, S> LRUCache<K, V, C, S>
+    where K: Eq + Hash + Copy + Decode,
+          V: Clone + Decode,
+          C: Clock,
+          S: StatsRecorder
+{
+    /// Sets the entries written by save in the cache in their
+    /// original order, keeping only the most recently used ones
+    /// if there are more entries than the capacity
+    ///
+    /// Nothing is set unless all of the input decodes.
+    pub fn restore<R: Read>(&mut self, mut reader: R) -> Result<(), PersistError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(PersistError::BadMagic);
+        }
+        let mut input = &data[MAGIC.len()..];
+
+        let version = u8::decode(&mut input)?;
+        if version != VERSION {
+            return Err(PersistError::UnsupportedVersion(version));
+        }
+
+        // The count is not trusted to preallocate since the
+        // entries run out first if it is corrupt
+        let count = u64::decode(&mut input)?;
+        let now = self.clock.now();
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let k = K::decode(&mut input)?;
+            let v = V::decode(&mut input)?;
+            let expires = match Option::<Duration>::decode(&mut input)? {
+                Some(ttl) => {
+                    Some(now.checked_add(ttl)
+                        .ok_or(PersistError::InvalidData("time to live is out of range"))?)
+                }
+                None => None,
+            };
+            entries.push((k, v, expires));
+        }
+        if !input.is_empty() {
+            return Err(PersistError::InvalidData("trailing bytes after the entries"));
+        }
 
-            self.add_to_front(new_node_ptr);
-            self.page_map.insert(k, new_node_ptr);
-            self.count += 1;
+        let skip = entries.len().saturating_sub(self.capacity);
+        for (k, v, expires) in entries.into_iter().skip(skip) {
+            self.insert(k, v, expires);
         }
+        Ok(())
     }
 }
 
-impl<K, V> Drop for LRUCache<K, V>
-    where K: Eq + Hash + Copy
+/// An iterator over the entries of a LRU cache
+pub struct Iter<'a, K: 'a, V: 'a> {
+    slots: &'a [Slot<K, V>],
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+
+        let node = self.slots[self.front].node();
+        self.front = node.prev;
+        Some((&node.key, &node.val))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+
+        let node = self.slots[self.back].node();
+        self.back = node.next;
+        Some((&node.key, &node.val))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+/// An iterator over the entries of a LRU cache
+/// with mutable references to the values
+///
+/// It holds a raw pointer to the slab because it hands out mutable
+/// references to several slots at once. Each slot is visited at
+/// most once, so the references never alias.
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    slots: *mut Slot<K, V>,
+    front: usize,
+    back: usize,
+    len: usize,
+    marker: PhantomData<&'a mut [Slot<K, V>]>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+
+        let node = unsafe { (*self.slots.add(self.front)).node_mut() };
+        self.front = node.prev;
+        Some((&node.key, &mut node.val))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+
+        let node = unsafe { (*self.slots.add(self.back)).node_mut() };
+        self.back = node.next;
+        Some((&node.key, &mut node.val))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+/// A view into a single entry of a LRU cache, which is either
+/// occupied by a value or vacant
+pub enum Entry<'a, K, V, C, S>
+    where K: 'a + Eq + Hash + Copy,
+          V: 'a,
+          C: 'a + Clock,
+          S: 'a + StatsRecorder
+{
+    Occupied(OccupiedEntry<'a, K, V, C, S>),
+    Vacant(VacantEntry<'a, K, V, C, S>),
+}
+
+/// An entry for a key that is in the cache
+pub struct OccupiedEntry<'a, K, V, C, S>
+    where K: 'a + Eq + Hash + Copy,
+          V: 'a,
+          C: 'a + Clock,
+          S: 'a + StatsRecorder
+{
+    cache: &'a mut LRUCache<K, V, C, S>,
+    node: usize,
+}
+
+/// An entry for a key that is not in the cache
+pub struct VacantEntry<'a, K, V, C, S>
+    where K: 'a + Eq + Hash + Copy,
+          V: 'a,
+          C: 'a + Clock,
+          S: 'a + StatsRecorder
+{
+    cache: &'a mut LRUCache<K, V, C, S>,
+    key: K,
+}
+
+impl<'a, K, V, C, S> Entry<'a, K, V, C, S>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          C: Clock,
+          S: StatsRecorder
 {
-    fn drop(&mut self) {
-        // Null out front and back pointers
-        self.front = ptr::null_mut();
-        self.back = ptr::null_mut();
-
-        // For every key in the hashmap, convert the pointer into a Box and let it drop
-        let keys: Vec<_> = self.page_map.keys().map(|key| key.clone()).collect();
-        for key in keys {
-            let node = self.page_map.remove(&key).unwrap();
-            unsafe {
-                mem::transmute::<*mut Node<K, V>, Box<Node<K, V>>>(node);
+    pub fn key(&self) -> K {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry) => entry.key(),
+        }
+    }
+
+    /// Sets the value if the entry is vacant and returns
+    /// a mutable reference to the entry's value
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Sets the value to the result of the function if the entry is
+    /// vacant and returns a mutable reference to the entry's value
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Calls the function on the value if the entry is occupied
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
             }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K, V, C, S> OccupiedEntry<'a, K, V, C, S>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          C: Clock,
+          S: StatsRecorder
+{
+    pub fn key(&self) -> K {
+        self.cache.slots[self.node].node().key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.cache.slots[self.node].node().val
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.cache.slots[self.node].node_mut().val
+    }
+
+    /// Converts the entry into a mutable reference to its value
+    /// that lives as long as the borrow of the cache
+    pub fn into_mut(self) -> &'a mut V {
+        let OccupiedEntry { cache, node } = self;
+        &mut cache.slots[node].node_mut().val
+    }
+
+    /// Replaces the value in place, restarting its time to live
+    /// like set does, and returns the old value
+    pub fn insert(&mut self, v: V) -> V {
+        let expires = self.cache.default_expiry();
+        self.cache.slots[self.node].node_mut().expires = expires;
+        self.cache.stats.record_update();
+        mem::replace(self.get_mut(), v)
+    }
+
+    /// Removes the entry from the cache and returns its value
+    pub fn remove(self) -> V {
+        self.cache.take(self.node)
+    }
+}
+
+impl<'a, K, V, C, S> VacantEntry<'a, K, V, C, S>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          C: Clock,
+          S: StatsRecorder
+{
+    pub fn key(&self) -> K {
+        self.key
+    }
+
+    /// Sets the entry's value, evicting the least recently used
+    /// entry if the cache is full, and returns a mutable reference to it
+    pub fn insert(self, v: V) -> &'a mut V {
+        let VacantEntry { cache, key } = self;
+        let expires = cache.default_expiry();
+        let n = cache.insert_new(key, v, expires);
+        &mut cache.slots[n].node_mut().val
+    }
+}
+
+impl<K, V, C, S> Cache<K, V> for LRUCache<K, V, C, S>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          C: Clock,
+          S: StatsRecorder
+{
+    fn get(&mut self, k: K) -> Option<V> {
+        LRUCache::get(self, k)
+    }
+
+    fn set(&mut self, k: K, v: V) {
+        LRUCache::set(self, k, v)
+    }
+
+    fn contains(&self, k: &K) -> bool {
+        match self.page_map.get(k) {
+            Some(&n) => !self.has_expired(n),
+            None => false,
         }
     }
+
+    fn len(&self) -> usize {
+        LRUCache::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        LRUCache::capacity(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
     use super::*;
 
+    /// A clock that only moves when the test advances it
+    #[derive(Clone)]
+    struct MockClock(Rc<Cell<Instant>>);
+
+    impl MockClock {
+        fn new() -> MockClock {
+            MockClock(Rc::new(Cell::new(Instant::now())))
+        }
+
+        fn advance(&self, d: Duration) {
+            self.0.set(self.0.get() + d);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    /// Checks that the links agree in both directions and with the
+    /// page map, and returns the keys from most to least recently used
+    fn linked_keys<K, V, C, S>(cache: &LRUCache<K, V, C, S>) -> Vec<K>
+        where K: Eq + Hash + Copy + ::std::fmt::Debug,
+              C: Clock,
+              S: StatsRecorder
+    {
+        let mut forward = Vec::new();
+        let mut n = cache.front;
+        while n != NIL {
+            let node = cache.slots[n].node();
+            assert_eq!(cache.page_map[&node.key], n);
+            forward.push(node.key);
+            n = node.prev;
+        }
+
+        let mut backward = Vec::new();
+        let mut n = cache.back;
+        while n != NIL {
+            let node = cache.slots[n].node();
+            backward.push(node.key);
+            n = node.next;
+        }
+        backward.reverse();
+
+        // Every slot is either linked or on the free list
+        let mut vacant = 0;
+        let mut n = cache.free;
+        while n != NIL {
+            n = match cache.slots[n] {
+                Slot::Vacant(next) => next,
+                Slot::Occupied(_) => panic!("occupied slot on the free list"),
+            };
+            vacant += 1;
+        }
+
+        assert_eq!(forward, backward);
+        assert_eq!(forward.len(), cache.count);
+        assert_eq!(cache.page_map.len(), cache.count);
+        assert_eq!(cache.count + vacant, cache.slots.len());
+        assert!(cache.slots.len() <= cache.capacity);
+        forward
+    }
+
     #[test]
     fn test_set_and_get() {
         let mut cache = LRUCache::new(10);
@@ -192,4 +1052,588 @@ mod tests {
         assert_eq!(cache.get(1), Some("1"));
         assert_eq!(cache.get(4), Some("4"));
     }
+
+    #[test]
+    fn test_ttl_expires_on_get() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(3, Some(Duration::from_secs(10)), clock.clone());
+        cache.set(1, "1");
+
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(cache.get(1), Some("1"));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_set_with_ttl_overrides_default() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(3, None, clock.clone());
+        cache.set(1, "1");
+        cache.set_with_ttl(2, "2", Duration::from_secs(5));
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.get(2), None);
+    }
+
+    #[test]
+    fn test_set_resets_ttl() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(3, Some(Duration::from_secs(10)), clock.clone());
+        cache.set(1, "1");
+
+        clock.advance(Duration::from_secs(8));
+        cache.set(1, "one");
+
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get(1), Some("one"));
+    }
+
+    #[test]
+    fn test_huge_ttl_never_expires() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(3, None, clock.clone());
+        cache.set_with_ttl(1, "1", Duration::MAX);
+
+        let mut default_ttl = LRUCache::with_clock(3, Some(Duration::MAX), clock.clone());
+        default_ttl.set(1, "1");
+        default_ttl.entry(2).or_insert("2");
+
+        clock.advance(Duration::from_secs(1_000_000_000));
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(default_ttl.get(1), Some("1"));
+        assert_eq!(default_ttl.get(2), Some("2"));
+        assert_eq!(default_ttl.purge_expired(), 0);
+
+        // The SystemClock constructors too
+        LRUCache::new(2).set_with_ttl(1, 1, Duration::MAX);
+        LRUCache::with_ttl(2, Duration::MAX).set(1, 1);
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(5, Some(Duration::from_secs(10)), clock.clone());
+        cache.set(1, "1");
+        cache.set(2, "2");
+        cache.set_with_ttl(3, "3", Duration::from_secs(30));
+
+        clock.advance(Duration::from_secs(5));
+        cache.set(4, "4");
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.purge_expired(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.purge_expired(), 0);
+
+        // Expired slots are free again so nothing live gets evicted
+        cache.set(5, "5");
+        cache.set(6, "6");
+        cache.set(7, "7");
+        assert_eq!(cache.get(3), Some("3"));
+        assert_eq!(cache.get(4), Some("4"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock_and_stats(2,
+                                                       Some(Duration::from_secs(10)),
+                                                       clock.clone(),
+                                                       CacheStats::default());
+        cache.set(1, "1");
+        cache.set(2, "2");
+        cache.set(2, "two");
+        cache.set(3, "3");
+
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(2), Some("two"));
+        assert_eq!(cache.get(3), Some("3"));
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.get(3), None);
+
+        assert_eq!(cache.stats(),
+                   CacheStats {
+                       hits: 2,
+                       misses: 2,
+                       insertions: 3,
+                       updates: 1,
+                       evictions: 2,
+                   });
+        assert_eq!(cache.stats().hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_reset_stats() {
+        let mut cache = LRUCache::with_stats(2);
+        cache.set(1, "1");
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.stats().hits, 1);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+        assert_eq!(cache.stats().hit_rate(), 0.0);
+
+        // Resetting only clears the counters
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_no_stats_takes_no_space() {
+        assert_eq!(mem::size_of::<NoStats>(), 0);
+        assert!(mem::size_of::<LRUCache<i32, i32>>() <
+                mem::size_of::<LRUCache<i32, i32, SystemClock, CacheStats>>());
+    }
+
+    #[test]
+    fn test_set_updates_in_place() {
+        let mut cache = LRUCache::new(2);
+        cache.set(1, "1");
+        cache.set(2, "2");
+
+        let node = cache.page_map[&1];
+        cache.set(1, "one");
+        assert_eq!(cache.page_map[&1], node);
+        assert_eq!(cache.front, node);
+
+        // 2 is now the least recently used key
+        cache.set(3, "3");
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some("one"));
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(4, None, clock.clone());
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        let slots = cache.slots.as_ptr();
+
+        for i in 4..1000 {
+            cache.set(i, i);
+        }
+        assert_eq!(linked_keys(&cache), vec![999, 998, 997, 996]);
+
+        // Removed and expired entries free their slots too
+        if let Entry::Occupied(entry) = cache.entry(998) {
+            entry.remove();
+        }
+        cache.set_with_ttl(1000, 1000, Duration::from_secs(1));
+        cache.set_with_ttl(1001, 1001, Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.purge_expired(), 2);
+        assert_eq!(linked_keys(&cache), vec![999, 997]);
+        for i in 1002..1005 {
+            cache.set(i, i);
+        }
+        assert_eq!(linked_keys(&cache), vec![1004, 1003, 1002, 999]);
+
+        // The slab never grew or moved
+        assert_eq!(cache.slots.len(), 4);
+        assert_eq!(cache.slots.as_ptr(), slots);
+    }
+
+    #[test]
+    fn test_entry_or_insert_with() {
+        let mut cache = LRUCache::new(2);
+        let mut calls = 0;
+
+        *cache.entry(1).or_insert_with(|| {
+            calls += 1;
+            10
+        }) += 1;
+        cache.entry(1).or_insert_with(|| {
+            calls += 1;
+            20
+        });
+
+        assert_eq!(calls, 1);
+        assert_eq!(cache.get(1), Some(11));
+    }
+
+    #[test]
+    fn test_entry_and_modify() {
+        let mut cache = LRUCache::new(2);
+        cache.entry(1).and_modify(|v| *v += 1).or_insert(0);
+        cache.entry(1).and_modify(|v| *v += 1).or_insert(0);
+        cache.entry(1).and_modify(|v| *v += 1).or_insert(0);
+        assert_eq!(cache.get(1), Some(2));
+    }
+
+    #[test]
+    fn test_entry_variants() {
+        let mut cache = LRUCache::new(2);
+        cache.set(1, "1");
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), 1);
+                assert_eq!(*entry.get(), "1");
+                assert_eq!(entry.insert("one"), "1");
+            }
+            Entry::Vacant(_) => panic!("1 should be occupied"),
+        }
+        assert_eq!(cache.get(1), Some("one"));
+
+        match cache.entry(2) {
+            Entry::Occupied(_) => panic!("2 should be vacant"),
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), 2);
+                assert_eq!(*entry.insert("2"), "2");
+            }
+        }
+        assert_eq!(cache.len(), 2);
+
+        match cache.entry(1) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), "one"),
+            Entry::Vacant(_) => panic!("1 should be occupied"),
+        }
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_entry_evicts_when_full() {
+        let mut cache = LRUCache::new(2);
+        cache.set(1, "1");
+        cache.set(2, "2");
+
+        // Looking up the entry for 1 makes 2 the least recently used key
+        cache.entry(1).or_insert("one");
+        cache.entry(3).or_insert("3");
+
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some("1"));
+        assert_eq!(cache.get(3), Some("3"));
+    }
+
+    #[test]
+    fn test_entry_for_expired_key_is_vacant() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(2, Some(Duration::from_secs(10)), clock.clone());
+        cache.set(1, "1");
+
+        clock.advance(Duration::from_secs(10));
+        match cache.entry(1) {
+            Entry::Occupied(_) => panic!("1 should have expired"),
+            Entry::Vacant(entry) => {
+                entry.insert("one");
+            }
+        }
+        assert_eq!(cache.len(), 1);
+
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(cache.get(1), Some("one"));
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cache = LRUCache::new(2);
+        assert_eq!(cache.get_or_insert_with(1, || "1"), "1");
+        assert_eq!(cache.get_or_insert_with(1, || "one"), "1");
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_zero_capacity_is_rejected() {
+        assert_eq!(LRUCache::<i32, i32>::try_new(0).err(), Some(CapacityError));
+        assert!(LRUCache::<i32, i32>::try_new(1).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "cache capacity must be at least one")]
+    fn test_new_panics_on_zero_capacity() {
+        LRUCache::<i32, i32>::new(0);
+    }
+
+    #[test]
+    fn test_capacity_one() {
+        let mut cache = LRUCache::new(1);
+        assert_eq!(cache.get(1), None);
+        assert!(cache.is_empty());
+
+        cache.set(1, "1");
+        assert_eq!(linked_keys(&cache), vec![1]);
+        cache.set(1, "one");
+        assert_eq!(linked_keys(&cache), vec![1]);
+        cache.set(2, "2");
+        assert_eq!(linked_keys(&cache), vec![2]);
+
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(2), Some("2"));
+        assert_eq!(cache.entry(3).or_insert("3"), &"3");
+        assert_eq!(linked_keys(&cache), vec![3]);
+    }
+
+    #[test]
+    fn test_eviction_boundary() {
+        let mut cache = LRUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+
+        // Filling up to the capacity does not evict anything
+        assert_eq!(linked_keys(&cache), vec![3, 2, 1]);
+
+        // Going one over evicts exactly the back
+        cache.set(4, 4);
+        assert_eq!(linked_keys(&cache), vec![4, 3, 2]);
+    }
+
+    #[test]
+    fn test_get_relinks_front_middle_and_back() {
+        let mut cache = LRUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+
+        cache.get(3);
+        assert_eq!(linked_keys(&cache), vec![3, 2, 1]);
+        cache.get(2);
+        assert_eq!(linked_keys(&cache), vec![2, 3, 1]);
+        cache.get(1);
+        assert_eq!(linked_keys(&cache), vec![1, 2, 3]);
+        assert_eq!(cache.get(4), None);
+        assert_eq!(linked_keys(&cache), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_remove_front_middle_and_back() {
+        for &(key, ref rest) in &[(3, vec![2, 1]), (2, vec![3, 1]), (1, vec![3, 2])] {
+            let mut cache = LRUCache::new(3);
+            cache.set(1, 1);
+            cache.set(2, 2);
+            cache.set(3, 3);
+
+            match cache.entry(key) {
+                Entry::Occupied(entry) => assert_eq!(entry.remove(), key),
+                Entry::Vacant(_) => panic!("{} should be occupied", key),
+            }
+            assert_eq!(&linked_keys(&cache), rest);
+        }
+
+        let mut cache = LRUCache::new(1);
+        cache.set(1, 1);
+        if let Entry::Occupied(entry) = cache.entry(1) {
+            entry.remove();
+        }
+        assert!(linked_keys(&cache).is_empty());
+        assert!(cache.front == NIL && cache.back == NIL);
+
+        // The emptied cache is still usable
+        cache.set(2, 2);
+        assert_eq!(linked_keys(&cache), vec![2]);
+    }
+
+    #[test]
+    fn test_purge_everything() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(3, Some(Duration::from_secs(1)), clock.clone());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.purge_expired(), 3);
+        assert!(linked_keys(&cache).is_empty());
+        assert!(cache.front == NIL && cache.back == NIL);
+
+        cache.set(4, 4);
+        assert_eq!(linked_keys(&cache), vec![4]);
+    }
+
+    #[test]
+    fn test_every_value_dropped_once() {
+        let value = Rc::new(());
+        let clock = MockClock::new();
+        {
+            let mut cache = LRUCache::with_clock(3, None, clock.clone());
+            for i in 0..5 {
+                cache.set(i, value.clone());
+            }
+            // Two evicted
+            assert_eq!(Rc::strong_count(&value), 4);
+
+            // An update drops the replaced value
+            cache.set(4, value.clone());
+            assert_eq!(Rc::strong_count(&value), 4);
+
+            // An expired entry is dropped when it is looked up
+            cache.set_with_ttl(5, value.clone(), Duration::from_secs(1));
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(Rc::strong_count(&value), 4);
+            assert_eq!(cache.get(5), None);
+            assert_eq!(Rc::strong_count(&value), 3);
+
+            // Removing an entry hands its value back
+            if let Entry::Occupied(entry) = cache.entry(4) {
+                drop(entry.remove());
+            }
+            assert_eq!(Rc::strong_count(&value), 2);
+        }
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        // Dropping an empty cache
+        let cache: LRUCache<i32, Rc<()>> = LRUCache::new(3);
+        drop(cache);
+    }
+
+    fn saved_cache() -> Vec<u8> {
+        let mut cache = LRUCache::new(4);
+        cache.set(1, String::from("1"));
+        cache.set(2, String::from("2"));
+        cache.set(3, String::from("3"));
+        cache.set(4, String::from("4"));
+        cache.get(2);
+        cache.get(1);
+        assert_eq!(linked_keys(&cache), vec![1, 2, 4, 3]);
+
+        let mut out = Vec::new();
+        cache.save(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_save_and_load_keeps_order() {
+        let saved = saved_cache();
+        let mut cache: LRUCache<i32, String> = LRUCache::load(&saved[..], 4).unwrap();
+
+        assert_eq!(linked_keys(&cache), vec![1, 2, 4, 3]);
+        assert_eq!(cache.get(4), Some(String::from("4")));
+    }
+
+    #[test]
+    fn test_load_keeps_most_recent_up_to_capacity() {
+        let saved = saved_cache();
+        let cache: LRUCache<i32, String> = LRUCache::load(&saved[..], 2).unwrap();
+
+        assert_eq!(linked_keys(&cache), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_save_keeps_remaining_ttl() {
+        let clock = MockClock::new();
+        let mut cache = LRUCache::with_clock(5, Some(Duration::from_secs(10)), clock.clone());
+        cache.set(1, 1u8);
+        cache.set(2, 2u8);
+        cache.set_with_ttl(3, 3u8, Duration::from_secs(30));
+        clock.advance(Duration::from_secs(5));
+        cache.set(4, 4u8);
+        cache.set_with_ttl(5, 5u8, Duration::from_secs(1));
+        clock.advance(Duration::from_secs(2));
+
+        let mut saved = Vec::new();
+        cache.save(&mut saved).unwrap();
+
+        let mut restored: LRUCache<i32, u8, _> = LRUCache::with_clock(4, None, clock.clone());
+        restored.restore(&saved[..]).unwrap();
+        // 5 had already expired so it was not saved
+        assert_eq!(linked_keys(&restored), vec![4, 3, 2, 1]);
+
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(restored.purge_expired(), 2);
+        assert_eq!(linked_keys(&restored), vec![4, 3]);
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_data() {
+        let saved = saved_cache();
+        for len in 0..saved.len() {
+            assert!(LRUCache::<i32, String>::load(&saved[..len], 4).is_err());
+        }
+
+        let mut bad_magic = saved.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(LRUCache::<i32, String>::load(&bad_magic[..], 4),
+                         Err(PersistError::BadMagic)));
+
+        let mut bad_version = saved.clone();
+        bad_version[4] = 99;
+        assert!(matches!(LRUCache::<i32, String>::load(&bad_version[..], 4),
+                         Err(PersistError::UnsupportedVersion(99))));
+
+        let mut trailing = saved.clone();
+        trailing.push(0);
+        assert!(matches!(LRUCache::<i32, String>::load(&trailing[..], 4),
+                         Err(PersistError::InvalidData(_))));
+
+        let mut huge_count = saved.clone();
+        huge_count[5..13].copy_from_slice(&[0xff; 8]);
+        assert!(matches!(LRUCache::<i32, String>::load(&huge_count[..], 4),
+                         Err(PersistError::Truncated)));
+
+        let mut huge_ttl = Vec::new();
+        huge_ttl.extend_from_slice(MAGIC);
+        huge_ttl.push(VERSION);
+        1u64.encode(&mut huge_ttl);
+        1i32.encode(&mut huge_ttl);
+        String::from("1").encode(&mut huge_ttl);
+        Some(Duration::new(u64::MAX, 0)).encode(&mut huge_ttl);
+        assert!(matches!(LRUCache::<i32, String>::load(&huge_ttl[..], 4),
+                         Err(PersistError::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_failed_restore_changes_nothing() {
+        let saved = saved_cache();
+        let mut cache = LRUCache::new(4);
+        cache.set(9, String::from("9"));
+
+        assert!(cache.restore(&saved[..saved.len() - 1]).is_err());
+        assert_eq!(linked_keys(&cache), vec![9]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_save_to_file() {
+        let path = ::std::env::temp_dir()
+            .join(format!("lru_cache_test_{}.bin", ::std::process::id()));
+
+        let mut cache = LRUCache::new(2);
+        cache.set(1, String::from("1"));
+        cache.set(2, String::from("2"));
+        cache.save_to_file(&path).unwrap();
+
+        let loaded: Result<LRUCache<i32, String>, _> = LRUCache::load_from_file(&path, 2);
+        let _ = ::std::fs::remove_file(&path);
+        assert_eq!(linked_keys(&loaded.unwrap()), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut cache = LRUCache::new(3);
+        assert_eq!(cache.iter().next(), None);
+        assert_eq!(cache.peek_lru(), None);
+
+        cache.set(1, 10);
+        cache.set(2, 20);
+        cache.set(3, 30);
+        cache.get(1);
+
+        let entries: Vec<_> = cache.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(entries, vec![(1, 10), (3, 30), (2, 20)]);
+        let keys: Vec<_> = cache.iter().rev().map(|(&k, _)| k).collect();
+        assert_eq!(keys, vec![2, 3, 1]);
+        assert_eq!(cache.iter().len(), 3);
+
+        // Meeting in the middle yields every entry once
+        let mut iter = cache.iter();
+        assert_eq!(iter.next(), Some((&1, &10)));
+        assert_eq!(iter.next_back(), Some((&2, &20)));
+        assert_eq!(iter.next(), Some((&3, &30)));
+        assert_eq!(iter.next_back(), None);
+
+        for (_, v) in cache.iter_mut() {
+            *v += 1;
+        }
+        assert_eq!(cache.peek_lru(), Some((2, &21)));
+        assert_eq!(linked_keys(&cache), vec![1, 3, 2]);
+    }
 }
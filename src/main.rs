@@ -1,9 +1,6 @@
-#![feature(alloc_system)]
-extern crate alloc_system;
+extern crate data_structures;
 
-pub mod lru_cache;
-
-use lru_cache::LRUCache;
+use data_structures::LRUCache;
 
 fn main() {
     // Test LRUCache for memory leaks:
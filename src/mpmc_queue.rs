@@ -0,0 +1,427 @@
+//! A lock-free multi producer, multi consumer queue.
+//!
+//! This is the Michael-Scott queue: a singly linked list with a
+//! dummy node at the head. Enqueuers link a node after the last
+//! node with a compare and swap and then try to swing the tail to
+//! it, and dequeuers swing the head forward. A thread that finds
+//! the tail lagging behind helps move it forward instead of
+//! waiting, so some thread always makes progress.
+//!
+//! Dequeued nodes can still be read by threads that loaded them
+//! just before they were unlinked, so they are not freed right away.
+//! Instead every operation publishes the nodes it is about to read
+//! as hazard pointers, and retired nodes are only freed once no
+//! hazard pointer points at them. The hazard pointer stores, their
+//! validation loads and the loads that scan them are all SeqCst,
+//! which is what guarantees that a thread either sees a node
+//! unlinked or has its hazard pointer seen by the reclaimer.
+
+use std::mem::MaybeUninit;
+use std::ptr;
+use sync::{AtomicBool, AtomicPtr, AtomicUsize, Ordering, UnsafeCell};
+
+/// Hazard pointers each operation needs at once
+const HAZARDS_PER_RECORD: usize = 2;
+
+struct Node<T> {
+    /// Uninitialized for the dummy node and after being dequeued
+    data: UnsafeCell<MaybeUninit<T>>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(data: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            data: UnsafeCell::new(data),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+/// A set of hazard pointers owned by one operation at a time
+struct HazardRecord<T> {
+    active: AtomicBool,
+    hazards: [AtomicPtr<Node<T>>; HAZARDS_PER_RECORD],
+    /// Nodes retired by the operations that owned this record
+    retired: UnsafeCell<Vec<*mut Node<T>>>,
+    /// Never changes after the record is added to the list
+    next: *mut HazardRecord<T>,
+}
+
+/// A queue that can be shared between any number of producer
+/// and consumer threads without locking
+pub struct MpmcQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    /// Hazard records are only freed when the queue is dropped
+    records: AtomicPtr<HazardRecord<T>>,
+    record_count: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for MpmcQueue<T> {}
+unsafe impl<T: Send> Sync for MpmcQueue<T> {}
+
+/// Gives an operation exclusive use of a hazard record and
+/// clears the record when the operation is done
+struct HazardGuard<'a, T: 'a> {
+    queue: &'a MpmcQueue<T>,
+    record: &'a HazardRecord<T>,
+}
+
+impl<'a, T> HazardGuard<'a, T> {
+    /// Publishes the pointer loaded from src as a hazard and returns
+    /// it once src is seen to still hold it
+    fn protect(&self, index: usize, src: &AtomicPtr<Node<T>>) -> *mut Node<T> {
+        let mut ptr = src.load(Ordering::SeqCst);
+        loop {
+            self.record.hazards[index].store(ptr, Ordering::SeqCst);
+            let current = src.load(Ordering::SeqCst);
+            if current == ptr {
+                return ptr;
+            }
+            ptr = current;
+        }
+    }
+
+    /// Frees the node once no hazard pointer points at it
+    fn retire(&self, node: *mut Node<T>) {
+        let threshold = 2 * HAZARDS_PER_RECORD * self.queue.record_count.load(Ordering::SeqCst);
+        self.record.retired.with_mut(|retired| {
+            let retired = unsafe { &mut *retired };
+            retired.push(node);
+            if retired.len() >= threshold {
+                self.queue.reclaim(retired);
+            }
+        });
+    }
+}
+
+impl<'a, T> Drop for HazardGuard<'a, T> {
+    fn drop(&mut self) {
+        for hazard in &self.record.hazards {
+            hazard.store(ptr::null_mut(), Ordering::SeqCst);
+        }
+        self.record.active.store(false, Ordering::Release);
+    }
+}
+
+impl<T> MpmcQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::new(MaybeUninit::uninit());
+        MpmcQueue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            records: AtomicPtr::new(ptr::null_mut()),
+            record_count: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn enqueue(&self, data: T) {
+        let node = Node::new(MaybeUninit::new(data));
+        let guard = self.hazard_guard();
+        loop {
+            let tail = guard.protect(0, &self.tail);
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if !next.is_null() {
+                // The tail is lagging behind, help move it forward
+                let _ = self.tail.compare_exchange(tail, next, Ordering::SeqCst, Ordering::Relaxed);
+                continue;
+            }
+
+            let linked = unsafe {
+                (*tail).next.compare_exchange(ptr::null_mut(),
+                                              node,
+                                              Ordering::Release,
+                                              Ordering::Relaxed)
+            };
+            if linked.is_ok() {
+                // Fine to fail, another thread already moved it
+                let _ = self.tail.compare_exchange(tail, node, Ordering::SeqCst, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let guard = self.hazard_guard();
+        loop {
+            let head = guard.protect(0, &self.head);
+            let next = unsafe { guard.protect(1, &(*head).next) };
+            // With head still the head, next can't have been retired
+            if self.head.load(Ordering::SeqCst) != head {
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+
+            // Never let the head pass the tail
+            let tail = self.tail.load(Ordering::SeqCst);
+            if head == tail {
+                let _ = self.tail.compare_exchange(tail, next, Ordering::SeqCst, Ordering::Relaxed);
+                continue;
+            }
+
+            if self.head
+                .compare_exchange(head, next, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok() {
+                // next is the new dummy node and only this thread
+                // moves its data out
+                let data = unsafe { (*next).data.with(|data| ptr::read((*data).as_ptr())) };
+                guard.retire(head);
+                return Some(data);
+            }
+        }
+    }
+
+    /// Returns true if the queue was empty at some point during the call
+    pub fn is_empty(&self) -> bool {
+        let guard = self.hazard_guard();
+        let head = guard.protect(0, &self.head);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+
+    /// Reuses an inactive hazard record or adds a new one
+    fn hazard_guard(&self) -> HazardGuard<'_, T> {
+        let mut record = self.records.load(Ordering::Acquire);
+        while !record.is_null() {
+            let existing = unsafe { &*record };
+            if !existing.active.load(Ordering::Relaxed) &&
+               existing.active
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok() {
+                return HazardGuard {
+                    queue: self,
+                    record: existing,
+                };
+            }
+            record = existing.next;
+        }
+
+        let record = Box::into_raw(Box::new(HazardRecord {
+            active: AtomicBool::new(true),
+            hazards: [AtomicPtr::new(ptr::null_mut()), AtomicPtr::new(ptr::null_mut())],
+            retired: UnsafeCell::new(vec![]),
+            next: self.records.load(Ordering::Relaxed),
+        }));
+        loop {
+            let next = unsafe { (*record).next };
+            match self.records.compare_exchange(next, record, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => unsafe { (*record).next = current },
+            }
+        }
+        self.record_count.fetch_add(1, Ordering::SeqCst);
+        HazardGuard {
+            queue: self,
+            record: unsafe { &*record },
+        }
+    }
+
+    /// Frees the retired nodes that no hazard pointer points at
+    fn reclaim(&self, retired: &mut Vec<*mut Node<T>>) {
+        let mut hazards = vec![];
+        let mut record = self.records.load(Ordering::Acquire);
+        while !record.is_null() {
+            let existing = unsafe { &*record };
+            for hazard in &existing.hazards {
+                let ptr = hazard.load(Ordering::SeqCst);
+                if !ptr.is_null() {
+                    hazards.push(ptr);
+                }
+            }
+            record = existing.next;
+        }
+
+        retired.retain(|&node| {
+            if hazards.contains(&node) {
+                return true;
+            }
+            // Retired nodes are former dummies whose data was moved out
+            unsafe { drop(Box::from_raw(node)) };
+            false
+        });
+    }
+}
+
+impl<T> Default for MpmcQueue<T> {
+    fn default() -> Self {
+        MpmcQueue::new()
+    }
+}
+
+impl<T> Drop for MpmcQueue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // The head is a dummy node and every node after it
+            // still holds its data
+            let mut node = self.head.load(Ordering::Relaxed);
+            let mut is_dummy = true;
+            while !node.is_null() {
+                let boxed = Box::from_raw(node);
+                if !is_dummy {
+                    boxed.data.with_mut(|data| ptr::drop_in_place((*data).as_mut_ptr()));
+                }
+                node = boxed.next.load(Ordering::Relaxed);
+                is_dummy = false;
+            }
+
+            let mut record = self.records.load(Ordering::Relaxed);
+            while !record.is_null() {
+                let boxed = Box::from_raw(record);
+                boxed.retired.with(|retired| {
+                    for &node in &*retired {
+                        drop(Box::from_raw(node));
+                    }
+                });
+                record = boxed.next;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::collections::HashSet;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_mpmc_queue() {
+        let queue = MpmcQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.dequeue(), None);
+        queue.enqueue(2);
+        queue.enqueue(3);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.dequeue(), Some(2));
+        assert_eq!(queue.dequeue(), Some(3));
+        queue.enqueue(5);
+        queue.enqueue(6);
+        assert_eq!(queue.dequeue(), Some(5));
+        assert_eq!(queue.dequeue(), Some(6));
+        assert_eq!(queue.dequeue(), None);
+    }
+
+    #[test]
+    fn test_drop_releases_queued_elements() {
+        let rc = Rc::new(());
+        {
+            let queue = MpmcQueue::new();
+            for _ in 0..100 {
+                queue.enqueue(rc.clone());
+            }
+            for _ in 0..40 {
+                queue.dequeue();
+            }
+            assert_eq!(Rc::strong_count(&rc), 61);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_retired_nodes_are_reclaimed() {
+        let queue = MpmcQueue::new();
+        for i in 0..1000 {
+            queue.enqueue(i);
+            queue.dequeue();
+        }
+        let record = unsafe { &*queue.records.load(Ordering::SeqCst) };
+        let retired = record.retired.with(|retired| unsafe { (*retired).len() });
+        assert!(retired < 2 * HAZARDS_PER_RECORD);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_many_producers_and_consumers() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        const PER_PRODUCER: usize = 20_000;
+
+        let queue = Arc::new(MpmcQueue::new());
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        queue.enqueue((p, i));
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut seen = vec![];
+                    let mut last = [None; PRODUCERS];
+                    while seen.len() < PRODUCERS * PER_PRODUCER / CONSUMERS {
+                        match queue.dequeue() {
+                            Some((p, i)) => {
+                                // Each producer's elements come out in order
+                                assert!(last[p].is_none_or(|last| i > last));
+                                last[p] = Some(i);
+                                seen.push((p, i));
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut all = HashSet::new();
+        for consumer in consumers {
+            for data in consumer.join().unwrap() {
+                assert!(all.insert(data), "{:?} dequeued twice", data);
+            }
+        }
+        assert_eq!(all.len(), PRODUCERS * PER_PRODUCER);
+        assert_eq!(queue.dequeue(), None);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom;
+    use sync::Arc;
+    use super::*;
+
+    #[test]
+    fn loom_concurrent_enqueue_dequeue() {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(2);
+        builder.check(|| {
+            let queue = Arc::new(MpmcQueue::new());
+            let producer = {
+                let queue = queue.clone();
+                loom::thread::spawn(move || {
+                    queue.enqueue(1);
+                    queue.enqueue(2);
+                })
+            };
+            let consumer = {
+                let queue = queue.clone();
+                loom::thread::spawn(move || queue.dequeue())
+            };
+
+            let first = queue.dequeue();
+            producer.join().unwrap();
+            let second = consumer.join().unwrap();
+
+            let mut seen: Vec<_> = vec![first, second, queue.dequeue(), queue.dequeue()]
+                .into_iter()
+                .filter_map(|data| data)
+                .collect();
+            seen.sort();
+            assert_eq!(seen, vec![1, 2]);
+        });
+    }
+}
@@ -0,0 +1,330 @@
+//! A compact binary encoding for saving cache contents to disk.
+//!
+//! Integers are written in little endian, and strings and
+//! vectors are prefixed with their length. Decoding reads from
+//! a byte slice and checks every length against the bytes that
+//! are left, so a corrupt or truncated file gives an error
+//! instead of a panic or a huge allocation.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::time::Duration;
+
+/// The error returned when saving or loading a cache fails
+#[derive(Debug)]
+pub enum PersistError {
+    Io(io::Error),
+    /// The data does not start with the expected header
+    BadMagic,
+    /// The data was written by a newer version of the format
+    UnsupportedVersion(u8),
+    /// The data ended in the middle of a value
+    Truncated,
+    /// The data has a value that cannot be decoded
+    InvalidData(&'static str),
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PersistError::Io(ref err) => write!(f, "i/o error: {}", err),
+            PersistError::BadMagic => write!(f, "not a cache file"),
+            PersistError::UnsupportedVersion(version) => {
+                write!(f, "unsupported cache file version {}", version)
+            }
+            PersistError::Truncated => write!(f, "cache file is truncated"),
+            PersistError::InvalidData(reason) => write!(f, "invalid cache file: {}", reason),
+        }
+    }
+}
+
+impl Error for PersistError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PersistError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PersistError {
+    fn from(err: io::Error) -> PersistError {
+        PersistError::Io(err)
+    }
+}
+
+/// A type that can be written into a cache file
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// A type that can be read back from a cache file
+pub trait Decode: Sized {
+    /// Decodes a value from the front of the input
+    /// and advances the input past it
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError>;
+}
+
+/// Splits the first n bytes off the input
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], PersistError> {
+    if input.len() < n {
+        return Err(PersistError::Truncated);
+    }
+    let (bytes, rest) = input.split_at(n);
+    *input = rest;
+    Ok(bytes)
+}
+
+/// Decodes a length prefix, checking that at least that many
+/// values of the given encoded size could follow it
+fn decode_len(input: &mut &[u8], min_size: usize) -> Result<usize, PersistError> {
+    let len = u64::decode(input)?;
+    if len > (input.len() / min_size.max(1)) as u64 {
+        return Err(PersistError::Truncated);
+    }
+    Ok(len as usize)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+                    let mut bytes = [0; mem::size_of::<$t>()];
+                    bytes.copy_from_slice(take(input, mem::size_of::<$t>())?);
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    }
+}
+
+impl_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+// usize and isize are always written as 64 bits so files can
+// move between platforms
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Decode for usize {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        let n = u64::decode(input)?;
+        if n > usize::MAX as u64 {
+            return Err(PersistError::InvalidData("usize out of range"));
+        }
+        Ok(n as usize)
+    }
+}
+
+impl Encode for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+}
+
+impl Decode for isize {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        let n = i64::decode(input)?;
+        if n > isize::MAX as i64 || n < isize::MIN as i64 {
+            return Err(PersistError::InvalidData("isize out of range"));
+        }
+        Ok(n as isize)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(PersistError::InvalidData("bool is not 0 or 1")),
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out);
+    }
+}
+
+impl Decode for char {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        ::std::char::from_u32(u32::decode(input)?)
+            .ok_or(PersistError::InvalidData("invalid char"))
+    }
+}
+
+impl Encode for &str {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_str().encode(out);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        let len = decode_len(input, 1)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| PersistError::InvalidData("string is not utf-8"))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        let len = decode_len(input, 1)?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            Some(ref val) => {
+                out.push(1);
+                val.encode(out);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            _ => Err(PersistError::InvalidData("option tag is not 0 or 1")),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        let a = A::decode(input)?;
+        let b = B::decode(input)?;
+        Ok((a, b))
+    }
+}
+
+impl Encode for Duration {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_secs().encode(out);
+        self.subsec_nanos().encode(out);
+    }
+}
+
+impl Decode for Duration {
+    fn decode(input: &mut &[u8]) -> Result<Self, PersistError> {
+        let secs = u64::decode(input)?;
+        let nanos = u32::decode(input)?;
+        if nanos >= 1_000_000_000 {
+            return Err(PersistError::InvalidData("duration nanoseconds out of range"));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use super::*;
+
+    fn round_trip<T: Encode + Decode + PartialEq + Debug>(val: T) {
+        let mut out = Vec::new();
+        val.encode(&mut out);
+
+        let mut input = &out[..];
+        assert_eq!(T::decode(&mut input).unwrap(), val);
+        assert!(input.is_empty());
+
+        // Every strict prefix is truncated
+        for len in 0..out.len() {
+            assert!(T::decode(&mut &out[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(0xabu8);
+        round_trip(-12345i16);
+        round_trip(0xdead_beefu32);
+        round_trip(i64::MIN);
+        round_trip(usize::MAX);
+        round_trip(-1isize);
+        round_trip(true);
+        round_trip('λ');
+        round_trip(String::from("hello"));
+        round_trip(vec![1u32, 2, 3]);
+        round_trip(Some(String::from("some")));
+        round_trip(None::<u8>);
+        round_trip((7u8, String::from("pair")));
+        round_trip(Duration::new(5, 999_999_999));
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(matches!(bool::decode(&mut &[2u8][..]),
+                         Err(PersistError::InvalidData(_))));
+        assert!(matches!(char::decode(&mut &[0u8, 0xd8, 0, 0][..]),
+                         Err(PersistError::InvalidData(_))));
+
+        let mut out = Vec::new();
+        2u64.encode(&mut out);
+        out.extend_from_slice(&[0xff, 0xfe]);
+        assert!(matches!(String::decode(&mut &out[..]),
+                         Err(PersistError::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_huge_length_is_truncated() {
+        let mut out = Vec::new();
+        u64::MAX.encode(&mut out);
+        assert!(matches!(Vec::<u8>::decode(&mut &out[..]), Err(PersistError::Truncated)));
+        assert!(matches!(String::decode(&mut &out[..]), Err(PersistError::Truncated)));
+    }
+}
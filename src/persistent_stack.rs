@@ -0,0 +1,297 @@
+//! An immutable stack that shares structure between versions.
+//!
+//! Pushing or popping returns a new version of the stack and
+//! leaves the old one untouched. Since a singly linked list never
+//! changes below its head, the new version just points at the
+//! nodes of the old one through a reference counted pointer, so
+//! keeping thousands of versions around costs one node per push
+//! instead of a copy of the whole stack each time.
+//!
+//! PersistentStack uses Rc, and ArcPersistentStack uses Arc so that
+//! versions can be shared between threads.
+
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+
+macro_rules! persistent_stack {
+    ($(#[$attr:meta])* $name:ident, $iter:ident, $node:ident, $ptr:ident) => {
+        struct $node<T> {
+            data: T,
+            next: Option<$ptr<$node<T>>>,
+        }
+
+        $(#[$attr])*
+        pub struct $name<T> {
+            len: usize,
+            head: Option<$ptr<$node<T>>>,
+        }
+
+        impl<T> $name<T> {
+            pub fn new() -> Self {
+                $name {
+                    len: 0,
+                    head: None,
+                }
+            }
+
+            /// Returns a new version of the stack with the data on top
+            pub fn push(&self, data: T) -> Self {
+                $name {
+                    len: self.len + 1,
+                    head: Some($ptr::new($node {
+                        data,
+                        next: self.head.clone(),
+                    })),
+                }
+            }
+
+            /// Returns a new version of the stack without its top,
+            /// or an empty stack if this one is empty
+            pub fn pop(&self) -> Self {
+                match self.head {
+                    Some(ref node) => {
+                        $name {
+                            len: self.len - 1,
+                            head: node.next.clone(),
+                        }
+                    }
+                    None => $name::new(),
+                }
+            }
+
+            /// Returns a reference to the top of the stack
+            pub fn peek(&self) -> Option<&T> {
+                self.head.as_ref().map(|node| &node.data)
+            }
+
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.head.is_none()
+            }
+
+            /// Iterates from the top of the stack to the bottom
+            pub fn iter(&self) -> $iter<'_, T> {
+                $iter {
+                    next: self.head.as_ref().map(|node| &**node),
+                    len: self.len,
+                }
+            }
+        }
+
+        impl<T> Drop for $name<T> {
+            fn drop(&mut self) {
+                // Only the nodes that no other version points to are
+                // dropped, one at a time so that a long stack doesn't
+                // overflow the thread's stack. into_inner rather than
+                // try_unwrap so that when two threads drop the last
+                // versions sharing a node at once, one of them still
+                // gets the node and keeps walking
+                let mut link = self.head.take();
+                while let Some(node) = link {
+                    match $ptr::into_inner(node) {
+                        Some(mut node) => link = node.next.take(),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        /// Cloning a version is O(1) since it shares every node
+        impl<T> Clone for $name<T> {
+            fn clone(&self) -> Self {
+                $name {
+                    len: self.len,
+                    head: self.head.clone(),
+                }
+            }
+        }
+
+        impl<T> Default for $name<T> {
+            fn default() -> Self {
+                $name::new()
+            }
+        }
+
+        impl<T: PartialEq> PartialEq for $name<T> {
+            fn eq(&self, other: &$name<T>) -> bool {
+                self.len == other.len && self.iter().eq(other.iter())
+            }
+        }
+
+        impl<T: Eq> Eq for $name<T> {}
+
+        /// Formats the stack from the top to the bottom
+        impl<T: fmt::Debug> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_list().entries(self.iter()).finish()
+            }
+        }
+
+        /// Iterator over references to the elements of a persistent stack
+        pub struct $iter<'a, T: 'a> {
+            next: Option<&'a $node<T>>,
+            len: usize,
+        }
+
+        impl<'a, T> Iterator for $iter<'a, T> {
+            type Item = &'a T;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.next.map(|node| {
+                    self.next = node.next.as_ref().map(|node| &**node);
+                    self.len -= 1;
+                    &node.data
+                })
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.len, Some(self.len))
+            }
+        }
+
+        impl<'a, T> ExactSizeIterator for $iter<'a, T> {}
+
+        impl<'a, T> IntoIterator for &'a $name<T> {
+            type Item = &'a T;
+            type IntoIter = $iter<'a, T>;
+
+            fn into_iter(self) -> $iter<'a, T> {
+                self.iter()
+            }
+        }
+    }
+}
+
+persistent_stack!(
+    /// A persistent stack whose versions share nodes through Rc
+    PersistentStack, Iter, Node, Rc);
+
+persistent_stack!(
+    /// A persistent stack whose versions share nodes through Arc
+    /// and can be sent between threads
+    ArcPersistentStack, ArcIter, ArcNode, Arc);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_push_and_pop() {
+        let empty = PersistentStack::new();
+        let one = empty.push(1);
+        let two = one.push(2);
+
+        assert_eq!(two.peek(), Some(&2));
+        assert_eq!(two.len(), 2);
+        assert_eq!(two.pop().peek(), Some(&1));
+        assert_eq!(two.pop().pop().peek(), None);
+        assert!(two.pop().pop().pop().is_empty());
+
+        // Older versions are untouched
+        assert_eq!(empty.len(), 0);
+        assert_eq!(one.iter().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(two.iter().collect::<Vec<_>>(), vec![&2, &1]);
+    }
+
+    #[test]
+    fn test_versions_share_tails() {
+        let base = PersistentStack::new().push(1).push(2);
+        let left = base.push(3);
+        let right = base.push(4);
+
+        let base_head = base.head.as_ref().unwrap();
+        assert!(Rc::ptr_eq(left.head.as_ref().unwrap().next.as_ref().unwrap(), base_head));
+        assert!(Rc::ptr_eq(right.head.as_ref().unwrap().next.as_ref().unwrap(), base_head));
+        assert!(Rc::ptr_eq(left.pop().head.as_ref().unwrap(), base_head));
+
+        // base, left and right all point at the node for 2
+        assert_eq!(Rc::strong_count(base_head), 3);
+        drop(left);
+        assert_eq!(Rc::strong_count(base.head.as_ref().unwrap()), 2);
+        assert_eq!(right.iter().collect::<Vec<_>>(), vec![&4, &2, &1]);
+    }
+
+    #[test]
+    fn test_traits() {
+        let stack = PersistentStack::new().push(1).push(2);
+        assert_eq!(stack.clone(), stack);
+        assert_eq!(format!("{:?}", stack), "[2, 1]");
+        assert_eq!(PersistentStack::<i32>::default(), PersistentStack::new());
+        assert!(stack != stack.pop());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_drop_long() {
+        let mut stack = PersistentStack::new();
+        for i in 0..10_000_000 {
+            stack = stack.push(i);
+        }
+        let snapshot = stack.pop();
+        drop(stack);
+
+        // Dropping a version keeps the nodes other versions still use
+        assert_eq!(snapshot.peek(), Some(&9_999_998));
+        drop(snapshot);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_arc_concurrent_drop_long() {
+        // The threads' stacks are far too small to drop the shared
+        // tail recursively, so whichever thread drops it last has
+        // to walk it iteratively
+        for _ in 0..20 {
+            let mut base = ArcPersistentStack::new();
+            for i in 0..100_000 {
+                base = base.push(i);
+            }
+
+            let barrier = Arc::new(Barrier::new(2));
+            let handles: Vec<_> = vec![base.push(-1), base.push(-2)]
+                .into_iter()
+                .map(|version| {
+                    let barrier = barrier.clone();
+                    thread::Builder::new()
+                        .stack_size(64 * 1024)
+                        .spawn(move || {
+                            barrier.wait();
+                            drop(version);
+                        })
+                        .unwrap()
+                })
+                .collect();
+            drop(base);
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_arc_versions_across_threads() {
+        let base = ArcPersistentStack::new().push(1).push(2);
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let base = base.clone();
+                thread::spawn(move || {
+                    let version = base.push(i);
+                    version.iter().cloned().collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), vec![i, 2, 1]);
+        }
+        assert_eq!(base.len(), 2);
+    }
+}
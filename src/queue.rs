@@ -6,6 +6,14 @@
 //! onto the second stack. It is completely safe but
 //! is less deterministic because of the occasional
 //! stack reversal.
+//!
+//! Every element is moved from the first stack to the
+//! second at most once, so a dequeue is amortized O(1)
+//! even though a single dequeue after n enqueues is O(n).
+
+use std::iter::{Chain, FromIterator, Rev};
+use std::slice;
+use std::vec;
 
 pub struct Queue<T> {
     stack1: Vec<T>,
@@ -20,25 +28,144 @@ impl<T> Queue<T> {
         }
     }
 
+    /// Creates a queue that can hold capacity elements
+    /// without reallocating
+    pub fn with_capacity(capacity: usize) -> Self {
+        Queue {
+            stack1: Vec::with_capacity(capacity),
+            stack2: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Worst case O(1) unless the first stack has to grow
     pub fn enqueue(&mut self, data: T) {
         self.stack1.push(data);
     }
 
+    /// Amortized O(1), worst case O(n) when the second stack
+    /// is empty and the first stack has to be reversed onto it
     pub fn dequeue(&mut self) -> Option<T> {
-        if self.stack2.len() == 0 {
-            if self.stack1.len() == 0 {
+        if self.stack2.is_empty() {
+            if self.stack1.is_empty() {
                 return None;
             }
 
-            // Reverse stack1 onto stack2
-            while self.stack1.len() > 0 {
-                self.stack2.push(self.stack1.pop().unwrap());
-            }
+            // Reverse stack1 onto stack2 in one pass
+            self.stack2.extend(self.stack1.drain(..).rev());
         }
-        return self.stack2.pop();
+        self.stack2.pop()
+    }
+
+    /// Returns the element that the next dequeue will return
+    pub fn peek_front(&self) -> Option<&T> {
+        self.stack2.last().or_else(|| self.stack1.first())
+    }
+
+    /// Returns the most recently enqueued element
+    pub fn peek_back(&self) -> Option<&T> {
+        self.stack1.last().or_else(|| self.stack2.first())
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack1.len() + self.stack2.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack1.is_empty() && self.stack2.is_empty()
+    }
+
+    /// Iterates from the front of the queue to the back
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { inner: self.stack2.iter().rev().chain(self.stack1.iter()) }
+    }
+
+    /// Removes every element, yielding them from the front
+    /// of the queue to the back
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { inner: self.stack2.drain(..).rev().chain(self.stack1.drain(..)) }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Queue::new()
+    }
+}
+
+impl<T> Extend<T> for Queue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.stack1.extend(iter);
+    }
+}
+
+impl<T> FromIterator<T> for Queue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Queue {
+            stack1: iter.into_iter().collect(),
+            stack2: vec![],
+        }
+    }
+}
+
+/// Iterator over references to the elements of a queue
+pub struct Iter<'a, T: 'a> {
+    inner: Chain<Rev<slice::Iter<'a, T>>, slice::Iter<'a, T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
     }
 }
 
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a Queue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Draining iterator over the elements of a queue
+pub struct Drain<'a, T: 'a> {
+    inner: Chain<Rev<vec::Drain<'a, T>>, vec::Drain<'a, T>>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Drain<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> {}
+
 #[test]
 fn test_queue() {
     let mut queue: Queue<i32> = Queue::new();
@@ -51,3 +178,54 @@ fn test_queue() {
     assert_eq!(queue.dequeue(), Some(5));
     assert_eq!(queue.dequeue(), Some(6));
 }
+
+#[test]
+fn test_queue_peek_and_len() {
+    let mut queue: Queue<i32> = Queue::with_capacity(4);
+    assert_eq!(queue.peek_front(), None);
+    assert_eq!(queue.peek_back(), None);
+    assert!(queue.is_empty());
+
+    queue.enqueue(1);
+    queue.enqueue(2);
+    assert_eq!(queue.peek_front(), Some(&1));
+    assert_eq!(queue.peek_back(), Some(&2));
+
+    // Moves 1 and 2 onto the second stack
+    assert_eq!(queue.dequeue(), Some(1));
+    assert_eq!(queue.peek_front(), Some(&2));
+    assert_eq!(queue.peek_back(), Some(&2));
+
+    queue.enqueue(3);
+    assert_eq!(queue.peek_front(), Some(&2));
+    assert_eq!(queue.peek_back(), Some(&3));
+    assert_eq!(queue.len(), 2);
+    assert!(!queue.is_empty());
+}
+
+#[test]
+fn test_queue_iter_spans_both_stacks() {
+    let mut queue: Queue<i32> = (1..4).collect();
+    assert_eq!(queue.dequeue(), Some(1));
+    queue.extend(4..6);
+
+    assert_eq!(queue.iter().collect::<Vec<_>>(), vec![&2, &3, &4, &5]);
+    assert_eq!(queue.iter().rev().collect::<Vec<_>>(), vec![&5, &4, &3, &2]);
+    assert_eq!(queue.iter().len(), 4);
+    assert_eq!((&queue).into_iter().count(), 4);
+}
+
+#[test]
+fn test_queue_drain() {
+    let mut queue: Queue<i32> = (1..4).collect();
+    assert_eq!(queue.dequeue(), Some(1));
+    queue.enqueue(4);
+
+    assert_eq!(queue.drain().collect::<Vec<_>>(), vec![2, 3, 4]);
+    assert!(queue.is_empty());
+    assert_eq!(queue.dequeue(), None);
+
+    // The queue is still usable after draining
+    queue.enqueue(5);
+    assert_eq!(queue.dequeue(), Some(5));
+}
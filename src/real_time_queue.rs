@@ -0,0 +1,315 @@
+//! A queue with worst case O(1) enqueue and dequeue.
+//!
+//! Like the two stack queue, elements are enqueued onto a rear
+//! stack and dequeued from a front stack. Instead of reversing
+//! the rear stack all at once when the front runs out, a rotation
+//! starts as soon as the rear stack becomes longer than the front
+//! (Hood and Melville's incremental rebuilding). The rotation pops
+//! the rear stack onto a new front while moving the old front
+//! aside, then puts the old front back on top of the new one. Every
+//! operation does a constant number of these steps, and starting
+//! early guarantees the rotation finishes before the old front is
+//! used up. Enqueues during a rotation go to a fresh rear stack.
+//!
+//! All buffers are sized when a rotation starts so no operation
+//! ever has to copy a growing buffer.
+
+use std::collections::VecDeque;
+use std::mem;
+
+/// Rotation steps done by every enqueue and dequeue
+const STEPS_PER_OP: usize = 3;
+
+/// A FIFO queue whose operations never pause for a reversal
+pub struct RealTimeQueue<T> {
+    len: usize,
+    /// Front stack, the oldest element is on top
+    front: Vec<T>,
+    /// Rear stack, the newest element is on top
+    rear: Vec<T>,
+    rotation: Option<Rotation<T>>,
+    /// Elements moved by the last operation
+    work: usize,
+}
+
+/// An in progress rotation of the old front and rear stacks
+/// into a new front stack
+struct Rotation<T> {
+    old_front: Vec<T>,
+    old_rear: Vec<T>,
+    /// Elements taken off the old front, oldest first
+    moved: VecDeque<T>,
+    new_front: Vec<T>,
+}
+
+impl<T> Rotation<T> {
+    /// Does one step of the rotation and returns the number of
+    /// elements it moved
+    fn step(&mut self) -> usize {
+        if let Some(data) = self.old_rear.pop() {
+            self.new_front.push(data);
+            match self.old_front.pop() {
+                Some(data) => {
+                    self.moved.push_back(data);
+                    2
+                }
+                None => 1,
+            }
+        } else if let Some(data) = self.moved.pop_back() {
+            self.new_front.push(data);
+            1
+        } else {
+            0
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.old_rear.is_empty() && self.moved.is_empty()
+    }
+
+    /// Returns the oldest element, which is always on the old front
+    /// until the rotation is done
+    fn oldest(&self) -> Option<&T> {
+        self.moved.front().or_else(|| self.old_front.last())
+    }
+
+    fn pop_oldest(&mut self) -> Option<T> {
+        self.moved.pop_front().or_else(|| self.old_front.pop())
+    }
+
+    /// Returns the newest element, which is the first one moved
+    /// onto the new front
+    fn newest(&self) -> Option<&T> {
+        self.new_front.first().or_else(|| self.old_rear.last())
+    }
+}
+
+impl<T> RealTimeQueue<T> {
+    pub fn new() -> Self {
+        RealTimeQueue {
+            len: 0,
+            front: vec![],
+            rear: vec![],
+            rotation: None,
+            work: 0,
+        }
+    }
+
+    pub fn enqueue(&mut self, data: T) {
+        self.rear.push(data);
+        self.len += 1;
+        self.work = 1;
+        self.rebalance();
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        let data = match self.rotation {
+            Some(ref mut rotation) => rotation.pop_oldest(),
+            None => self.front.pop(),
+        };
+        if data.is_some() {
+            self.len -= 1;
+            self.work = 1;
+            self.rebalance();
+        } else {
+            self.work = 0;
+        }
+        data
+    }
+
+    /// Returns the element that the next dequeue will return
+    pub fn peek_front(&self) -> Option<&T> {
+        match self.rotation {
+            Some(ref rotation) => rotation.oldest(),
+            None => self.front.last(),
+        }
+    }
+
+    /// Returns the most recently enqueued element
+    pub fn peek_back(&self) -> Option<&T> {
+        self.rear.last().or_else(|| match self.rotation {
+            Some(ref rotation) => rotation.newest(),
+            None => self.front.first(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Advances the current rotation, or starts one if the rear
+    /// stack has grown longer than the front stack
+    fn rebalance(&mut self) {
+        if self.rotation.is_none() {
+            if self.rear.len() <= self.front.len() {
+                return;
+            }
+            self.start_rotation();
+        }
+
+        let done = {
+            let rotation = self.rotation.as_mut().unwrap();
+            for _ in 0..STEPS_PER_OP {
+                self.work += rotation.step();
+            }
+            rotation.is_done()
+        };
+        if done {
+            let rotation = self.rotation.take().unwrap();
+            self.front = rotation.new_front;
+        }
+    }
+
+    fn start_rotation(&mut self) {
+        let front_len = self.front.len();
+        let rear_len = self.rear.len();
+
+        // The rear can grow until it is one longer than the new front
+        let new_rear = Vec::with_capacity(front_len + rear_len + 1);
+        self.rotation = Some(Rotation {
+            old_front: mem::take(&mut self.front),
+            old_rear: mem::replace(&mut self.rear, new_rear),
+            moved: VecDeque::with_capacity(front_len),
+            new_front: Vec::with_capacity(front_len + rear_len),
+        });
+    }
+}
+
+impl<T> Default for RealTimeQueue<T> {
+    fn default() -> Self {
+        RealTimeQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use super::*;
+
+    /// The most elements a single operation may move
+    const MAX_WORK: usize = 1 + 2 * STEPS_PER_OP;
+
+    /// Deterministic xorshift so the tests don't need a rand crate
+    fn next_random(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    #[test]
+    fn test_real_time_queue() {
+        let mut queue = RealTimeQueue::new();
+        assert_eq!(queue.dequeue(), None);
+        queue.enqueue(2);
+        queue.enqueue(3);
+        assert_eq!(queue.dequeue(), Some(2));
+        assert_eq!(queue.dequeue(), Some(3));
+        queue.enqueue(5);
+        queue.enqueue(6);
+        assert_eq!(queue.dequeue(), Some(5));
+        assert_eq!(queue.dequeue(), Some(6));
+        assert_eq!(queue.dequeue(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_peek_during_rotation() {
+        let mut queue = RealTimeQueue::new();
+        for i in 0..20 {
+            queue.enqueue(i);
+            assert_eq!(queue.peek_front(), Some(&0));
+            assert_eq!(queue.peek_back(), Some(&i));
+        }
+        for i in 0..20 {
+            assert_eq!(queue.peek_front(), Some(&i));
+            assert_eq!(queue.peek_back(), Some(&19));
+            assert_eq!(queue.dequeue(), Some(i));
+        }
+        assert_eq!(queue.peek_front(), None);
+        assert_eq!(queue.peek_back(), None);
+    }
+
+    #[test]
+    fn test_work_per_operation_is_bounded() {
+        let mut queue = RealTimeQueue::new();
+
+        // A long run of enqueues followed by dequeues is the worst
+        // case for the two stack queue
+        for i in 0..100_000 {
+            queue.enqueue(i);
+            assert!(queue.work <= MAX_WORK);
+        }
+        for i in 0..100_000 {
+            assert_eq!(queue.dequeue(), Some(i));
+            assert!(queue.work <= MAX_WORK);
+        }
+        assert_eq!(queue.dequeue(), None);
+    }
+
+    #[test]
+    fn test_matches_vec_deque_with_bounded_work() {
+        let mut queue = RealTimeQueue::new();
+        let mut expected = VecDeque::new();
+        let mut state = 0x2545_f491;
+        let mut next = 0;
+
+        for _ in 0..200_000 {
+            // Biased towards enqueues so the queue keeps growing
+            if next_random(&mut state) % 5 < 3 {
+                queue.enqueue(next);
+                expected.push_back(next);
+                next += 1;
+            } else {
+                assert_eq!(queue.dequeue(), expected.pop_front());
+            }
+            assert!(queue.work <= MAX_WORK);
+            assert_eq!(queue.len(), expected.len());
+            assert_eq!(queue.peek_front(), expected.front());
+            assert_eq!(queue.peek_back(), expected.back());
+        }
+
+        while let Some(data) = expected.pop_front() {
+            assert_eq!(queue.dequeue(), Some(data));
+            assert!(queue.work <= MAX_WORK);
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_buffers_never_grow() {
+        let mut queue = RealTimeQueue::new();
+        let mut state = 0x1234_5678;
+
+        for i in 0..50_000 {
+            let rotating = queue.rotation.is_some();
+            let front = queue.front.as_ptr();
+            let rear = queue.rear.as_ptr();
+            let new_front = queue.rotation.as_ref().map(|r| r.new_front.as_ptr());
+
+            if next_random(&mut state).is_multiple_of(3) {
+                queue.dequeue();
+            } else {
+                queue.enqueue(i);
+            }
+
+            // Buffers are only replaced when a rotation starts, and
+            // were sized so they never have to be reallocated
+            if rotating {
+                assert_eq!(queue.rear.as_ptr(), rear);
+                match queue.rotation {
+                    Some(ref rotation) => {
+                        assert_eq!(Some(rotation.new_front.as_ptr()), new_front)
+                    }
+                    None => assert_eq!(Some(queue.front.as_ptr()), new_front),
+                }
+            } else if queue.rotation.is_none() && queue.front.as_ptr() == front {
+                assert_eq!(queue.rear.as_ptr(), rear);
+            }
+        }
+    }
+}
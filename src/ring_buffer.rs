@@ -0,0 +1,385 @@
+//! A fixed capacity queue stored in a circular buffer.
+//!
+//! The buffer is allocated once when the queue is created. The
+//! queue keeps the index of its oldest element and its length,
+//! and both wrap around the end of the buffer, so pushing and
+//! popping never move or allocate anything. When the buffer is
+//! full the caller decides between getting the new element back
+//! (backpressure) or overwriting the oldest element.
+
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::iter::Chain;
+use std::ptr;
+use std::slice;
+
+/// A bounded FIFO queue that never allocates after it is created
+pub struct RingBuffer<T> {
+    buf: Box<[MaybeUninit<T>]>,
+    /// Index of the oldest element
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingBuffer<T> {
+    /// Creates a ring buffer that holds up to capacity elements.
+    /// Panics if the capacity is zero
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "ring buffer capacity must be at least one");
+        let buf: Vec<MaybeUninit<T>> = (0..capacity).map(|_| MaybeUninit::uninit()).collect();
+        RingBuffer {
+            buf: buf.into_boxed_slice(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    /// Pushes the data onto the back of the queue, or hands it
+    /// back if the queue is full
+    pub fn try_push(&mut self, data: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(data);
+        }
+        let tail = self.wrap(self.head + self.len);
+        self.buf[tail] = MaybeUninit::new(data);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Pushes the data onto the back of the queue, removing and
+    /// returning the oldest element if the queue is full
+    pub fn push_overwrite(&mut self, data: T) -> Option<T> {
+        if !self.is_full() {
+            let _ = self.try_push(data);
+            return None;
+        }
+        // The slot of the oldest element becomes the new tail
+        let old = unsafe { ptr::read(self.buf[self.head].as_ptr()) };
+        self.buf[self.head] = MaybeUninit::new(data);
+        self.head = self.wrap(self.head + 1);
+        Some(old)
+    }
+
+    /// Removes the oldest element
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let data = unsafe { ptr::read(self.buf[self.head].as_ptr()) };
+        self.head = self.wrap(self.head + 1);
+        self.len -= 1;
+        Some(data)
+    }
+
+    /// Returns the element that the next pop will return
+    pub fn peek_front(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    /// Returns the most recently pushed element
+    pub fn peek_back(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.drain();
+    }
+
+    /// Returns the elements from oldest to newest as two slices,
+    /// the second of which is empty unless the elements wrap around
+    /// the end of the buffer
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (first, second) = self.ranges();
+        let ptr = self.buf.as_ptr() as *const T;
+        unsafe {
+            (slice::from_raw_parts(ptr.add(first.0), first.1),
+             slice::from_raw_parts(ptr.add(second.0), second.1))
+        }
+    }
+
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let (first, second) = self.ranges();
+        let ptr = self.buf.as_mut_ptr() as *mut T;
+        unsafe {
+            (slice::from_raw_parts_mut(ptr.add(first.0), first.1),
+             slice::from_raw_parts_mut(ptr.add(second.0), second.1))
+        }
+    }
+
+    /// Iterates from the oldest element to the newest
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (first, second) = self.as_slices();
+        Iter { inner: first.iter().chain(second.iter()) }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let (first, second) = self.as_mut_slices();
+        IterMut { inner: first.iter_mut().chain(second.iter_mut()) }
+    }
+
+    /// Removes every element, yielding them from oldest to newest.
+    /// Elements that are not consumed are dropped with the iterator
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { ring: self }
+    }
+
+    fn wrap(&self, index: usize) -> usize {
+        if index >= self.capacity() {
+            index - self.capacity()
+        } else {
+            index
+        }
+    }
+
+    /// Returns the (start, len) of the occupied parts of the buffer
+    fn ranges(&self) -> ((usize, usize), (usize, usize)) {
+        let to_end = self.capacity() - self.head;
+        if self.len <= to_end {
+            ((self.head, self.len), (0, 0))
+        } else {
+            ((self.head, to_end), (0, self.len - to_end))
+        }
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let (first, second) = self.as_mut_slices();
+        unsafe {
+            ptr::drop_in_place(first);
+            ptr::drop_in_place(second);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over references to the elements of a ring buffer
+pub struct Iter<'a, T: 'a> {
+    inner: Chain<slice::Iter<'a, T>, slice::Iter<'a, T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// Iterator over mutable references to the elements of a ring buffer
+pub struct IterMut<'a, T: 'a> {
+    inner: Chain<slice::IterMut<'a, T>, slice::IterMut<'a, T>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a RingBuffer<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut RingBuffer<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// Draining iterator over the elements of a ring buffer
+pub struct Drain<'a, T: 'a> {
+    ring: &'a mut RingBuffer<T>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ring.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.ring.len(), Some(self.ring.len()))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> {}
+
+impl<'a, T> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        while self.ring.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut ring = RingBuffer::new(3);
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.try_push(1), Ok(()));
+        assert_eq!(ring.try_push(2), Ok(()));
+        assert_eq!(ring.pop(), Some(1));
+        assert_eq!(ring.try_push(3), Ok(()));
+        assert_eq!(ring.try_push(4), Ok(()));
+        assert!(ring.is_full());
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), Some(3));
+        assert_eq!(ring.pop(), Some(4));
+        assert_eq!(ring.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_capacity_panics() {
+        RingBuffer::<i32>::new(0);
+    }
+
+    #[test]
+    fn test_try_push_hands_back_when_full() {
+        let mut ring = RingBuffer::new(2);
+        ring.try_push("a").unwrap();
+        ring.try_push("b").unwrap();
+        assert_eq!(ring.try_push("c"), Err("c"));
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.iter().collect::<Vec<_>>(), vec![&"a", &"b"]);
+    }
+
+    #[test]
+    fn test_push_overwrite_drops_oldest() {
+        let mut ring = RingBuffer::new(3);
+        for i in 0..3 {
+            assert_eq!(ring.push_overwrite(i), None);
+        }
+        assert_eq!(ring.push_overwrite(3), Some(0));
+        assert_eq!(ring.push_overwrite(4), Some(1));
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.peek_front(), Some(&2));
+        assert_eq!(ring.peek_back(), Some(&4));
+        assert_eq!(ring.drain().collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_as_slices_wrap_around() {
+        let mut ring = RingBuffer::new(4);
+        for i in 0..4 {
+            ring.try_push(i).unwrap();
+        }
+        assert_eq!(ring.as_slices(), (&[0, 1, 2, 3][..], &[][..]));
+
+        ring.pop();
+        ring.pop();
+        ring.try_push(4).unwrap();
+        assert_eq!(ring.as_slices(), (&[2, 3][..], &[4][..]));
+
+        for x in ring.iter_mut() {
+            *x *= 10;
+        }
+        assert_eq!(ring.iter().rev().collect::<Vec<_>>(), vec![&40, &30, &20]);
+        assert_eq!(format!("{:?}", ring), "[20, 30, 40]");
+    }
+
+    #[test]
+    fn test_drain_drops_remaining() {
+        let rc = Rc::new(());
+        let mut ring = RingBuffer::new(4);
+        for _ in 0..4 {
+            ring.try_push(rc.clone()).unwrap();
+        }
+        {
+            let mut drain = ring.drain();
+            assert!(drain.next().is_some());
+        }
+        assert!(ring.is_empty());
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        // The buffer is reused after draining
+        ring.try_push(rc.clone()).unwrap();
+        ring.clear();
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_drop_and_overwrite_release_elements() {
+        let rc = Rc::new(());
+        {
+            let mut ring = RingBuffer::new(3);
+            for _ in 0..10 {
+                drop(ring.push_overwrite(rc.clone()));
+            }
+            ring.pop();
+            assert_eq!(Rc::strong_count(&rc), 3);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_zero_sized_elements() {
+        let mut ring = RingBuffer::new(2);
+        ring.try_push(()).unwrap();
+        ring.try_push(()).unwrap();
+        assert_eq!(ring.try_push(()), Err(()));
+        assert_eq!(ring.push_overwrite(()), Some(()));
+        assert_eq!(ring.iter().count(), 2);
+    }
+}
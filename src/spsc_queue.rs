@@ -0,0 +1,339 @@
+//! A wait-free single producer, single consumer queue.
+//!
+//! The queue is a ring buffer shared between one Producer and one
+//! Consumer. The producer only writes the tail counter and the
+//! consumer only writes the head counter, so neither ever has to
+//! retry: a push or pop is a bounded number of steps no matter
+//! what the other side is doing.
+//!
+//! The head and tail are indices modulo twice the capacity, so a
+//! full queue (the tail one capacity ahead) can be told apart from
+//! an empty one (the tail equal to the head) and the indices wrap
+//! at the same point for any capacity. The producer writes a slot and then
+//! publishes it with a Release store of the tail, which the consumer
+//! reads with Acquire before reading the slot. The consumer hands
+//! the slot back the same way through the head.
+
+use std::mem::MaybeUninit;
+use std::ptr;
+use sync::{Arc, AtomicUsize, Ordering, UnsafeCell};
+
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Index of the next element to pop, modulo twice the capacity
+    head: AtomicUsize,
+    /// Index of the next element to push, modulo twice the capacity
+    tail: AtomicUsize,
+}
+
+impl<T> Buffer<T> {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: usize) -> &UnsafeCell<MaybeUninit<T>> {
+        if index < self.capacity() {
+            &self.slots[index]
+        } else {
+            &self.slots[index - self.capacity()]
+        }
+    }
+
+    /// The index after the given one
+    fn next(&self, index: usize) -> usize {
+        if index + 1 == 2 * self.capacity() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    /// Number of elements between the head and tail indices
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            2 * self.capacity() - (head - tail)
+        }
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        self.distance(head, tail)
+    }
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        // Both handles are gone, so every pushed element that was
+        // not popped is still in its slot
+        let mut head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        while head != tail {
+            self.slot(head).with_mut(|slot| unsafe { ptr::drop_in_place((*slot).as_mut_ptr()) });
+            head = self.next(head);
+        }
+    }
+}
+
+/// The sending half of a single producer, single consumer queue
+pub struct Producer<T> {
+    buffer: Arc<Buffer<T>>,
+}
+
+/// The receiving half of a single producer, single consumer queue
+pub struct Consumer<T> {
+    buffer: Arc<Buffer<T>>,
+}
+
+// Each half is the only one allowed to push or pop, and both
+// require &mut self to do so, so sharing a reference to a half
+// between threads is harmless
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Sync for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+unsafe impl<T: Send> Sync for Consumer<T> {}
+
+/// Creates a queue that holds up to capacity elements and returns
+/// its two halves. Panics if the capacity is zero or too large for
+/// twice it to fit in a usize
+pub fn new<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "queue capacity must be at least one");
+    assert!(capacity <= usize::MAX / 2, "queue capacity is too large");
+    let slots: Vec<_> = (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+    let buffer = Arc::new(Buffer {
+        slots: slots.into_boxed_slice(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Producer { buffer: buffer.clone() }, Consumer { buffer })
+}
+
+impl<T> Producer<T> {
+    /// Pushes the data onto the queue, or hands it back if the
+    /// queue is full
+    pub fn push(&mut self, data: T) -> Result<(), T> {
+        let buffer = &*self.buffer;
+        let tail = buffer.tail.load(Ordering::Relaxed);
+        let head = buffer.head.load(Ordering::Acquire);
+        if buffer.distance(head, tail) == buffer.capacity() {
+            return Err(data);
+        }
+
+        buffer.slot(tail).with_mut(|slot| unsafe { ptr::write(slot, MaybeUninit::new(data)) });
+        buffer.tail.store(buffer.next(tail), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Number of elements in the queue, which can already be
+    /// smaller if the consumer is popping
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Removes the oldest element, or returns None if the queue is empty
+    pub fn pop(&mut self) -> Option<T> {
+        let buffer = &*self.buffer;
+        let head = buffer.head.load(Ordering::Relaxed);
+        let tail = buffer.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let data = buffer.slot(head).with(|slot| unsafe { ptr::read((*slot).as_ptr()) });
+        buffer.head.store(buffer.next(head), Ordering::Release);
+        Some(data)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Number of elements in the queue, which can already be
+    /// larger if the producer is pushing
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_spsc_queue() {
+        let (mut producer, mut consumer) = new(2);
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.len(), 2);
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(3), Ok(()));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn test_indices_wrap_with_any_capacity() {
+        let (mut producer, mut consumer) = new(3);
+        let mut next_push = 0;
+        let mut next_pop = 0;
+        for round in 0..100 {
+            // Fill to a varying level so the wrap point is crossed
+            // both while full and while partly empty
+            while producer.len() < 1 + round % 3 {
+                producer.push(next_push).unwrap();
+                next_push += 1;
+            }
+            while consumer.len() > round % 2 {
+                assert_eq!(consumer.pop(), Some(next_pop));
+                next_pop += 1;
+            }
+
+            let buffer = &consumer.buffer;
+            assert!(buffer.head.load(Ordering::Relaxed) < 6);
+            assert!(buffer.tail.load(Ordering::Relaxed) < 6);
+        }
+        while let Some(data) = consumer.pop() {
+            assert_eq!(data, next_pop);
+            next_pop += 1;
+        }
+        assert_eq!(next_pop, next_push);
+    }
+
+    #[test]
+    fn test_drop_releases_unpopped_elements() {
+        let rc = Rc::new(());
+        {
+            let (mut producer, mut consumer) = new(4);
+            for _ in 0..3 {
+                producer.push(rc.clone()).unwrap();
+            }
+            consumer.pop();
+            drop(producer);
+            assert_eq!(Rc::strong_count(&rc), 3);
+        }
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_threads_keep_fifo_order() {
+        const COUNT: usize = 200_000;
+        let (mut producer, mut consumer) = new(64);
+
+        let handle = thread::spawn(move || {
+            for i in 0..COUNT {
+                let mut data = i;
+                while let Err(rejected) = producer.push(data) {
+                    data = rejected;
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < COUNT {
+            match consumer.pop() {
+                Some(data) => {
+                    assert_eq!(data, expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        handle.join().unwrap();
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_every_element_dropped_once_across_threads() {
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut producer, mut consumer) = new(8);
+        let producer_drops = drops.clone();
+        let handle = thread::spawn(move || {
+            for _ in 0..504 {
+                let mut data = Counted(producer_drops.clone());
+                while let Err(rejected) = producer.push(data) {
+                    data = rejected;
+                    thread::yield_now();
+                }
+            }
+        });
+
+        // Leave the last four queued for the buffer to drop
+        for _ in 0..500 {
+            while consumer.pop().is_none() {
+                thread::yield_now();
+            }
+        }
+        handle.join().unwrap();
+        drop(consumer);
+        assert_eq!(drops.load(Ordering::SeqCst), 504);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom;
+    use super::*;
+
+    #[test]
+    fn loom_push_pop() {
+        loom::model(|| {
+            let (mut producer, mut consumer) = new(2);
+            let handle = loom::thread::spawn(move || {
+                for i in 0..3 {
+                    let mut data = i;
+                    while let Err(rejected) = producer.push(data) {
+                        data = rejected;
+                        loom::thread::yield_now();
+                    }
+                }
+            });
+
+            for i in 0..3 {
+                loop {
+                    match consumer.pop() {
+                        Some(data) => {
+                            assert_eq!(data, i);
+                            break;
+                        }
+                        None => loom::thread::yield_now(),
+                    }
+                }
+            }
+            handle.join().unwrap();
+        });
+    }
+}
@@ -4,7 +4,9 @@
 //! so you can just use the default primitives like Box without
 //! having to use reference counting or pointers.
 
+use std::fmt;
 use std::fmt::Debug;
+use std::iter::FromIterator;
 
 type Link<T> = Option<Box<Node<T>>>;
 
@@ -16,11 +18,11 @@ struct Node<T> {
 
 /// Stack implementation using a singly linked list
 pub struct Stack<T> {
-    size: i32,
+    size: usize,
     head: Link<T>,
 }
 
-impl<T: Debug> Stack<T> {
+impl<T> Stack<T> {
     pub fn new() -> Self {
         Stack {
             size: 0,
@@ -35,7 +37,7 @@ impl<T: Debug> Stack<T> {
         // This is useful for making sure there is only one
         // mutable reference to memory
         let new_head = Some(Box::new(Node {
-            data: data,
+            data,
             next: self.head.take(),
         }));
 
@@ -56,11 +58,63 @@ impl<T: Debug> Stack<T> {
         })
     }
 
-    // does the same thing as print_stack but inside the class
-    // for notes about how it works, refer to print_stack
+    /// Returns a reference to the top of the stack
+    pub fn peek(&self) -> Option<&T> {
+        // Rust note: as_ref() turns a &Option<T> into an Option<&T>
+        // so that map doesn't move the node out of the stack
+        self.head.as_ref().map(|node| &node.data)
+    }
+
+    /// Returns a mutable reference to the top of the stack
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.head.as_mut().map(|node| &mut node.data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// Removes every element from the stack
+    pub fn clear(&mut self) {
+        // Unlink the nodes one at a time so that dropping the
+        // head doesn't recursively drop the rest of the list
+        let mut link = self.head.take();
+        while let Some(mut node) = link {
+            link = node.next.take();
+        }
+        self.size = 0;
+    }
+
+    /// Iterates from the top of the stack to the bottom
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.as_deref(),
+            len: self.size,
+        }
+    }
+
+    /// Iterates from the top of the stack to the bottom
+    /// with mutable references
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head.as_deref_mut(),
+            len: self.size,
+        }
+    }
+}
+
+impl<T: Debug> Stack<T> {
+    /// Prints the stack from the top to the bottom
     pub fn print(&self) {
+        // does the same thing as print_stack but inside the class
+        // for notes about how it works, refer to print_stack
         let mut counter = &self.head;
         while let Some(ref n) = *counter {
+            println!("{:?}", n.data);
             counter = &n.next;
         }
     }
@@ -71,6 +125,7 @@ impl<T: Debug> Stack<T> {
 /// recurse to the child
 fn print_stack_node<T: Debug>(n: &Link<T>) {
     if let Some(ref node) = *n {
+        println!("{:?}", node.data);
         print_stack_node(&node.next);
     }
 }
@@ -85,11 +140,165 @@ pub fn print_stack<T: Debug>(s: &Stack<T>) {
     // let mut means you can reassign it to other variables
     let mut counter = &s.head;
     while let Some(ref node) = *counter {
+        println!("{:?}", node.data);
         counter = &node.next;
     }
 }
 
+/// Iterator over references to the elements of a stack
+pub struct Iter<'a, T: 'a> {
+    next: Option<&'a Node<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            self.len -= 1;
+            &node.data
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// Iterator over mutable references to the elements of a stack
+pub struct IterMut<'a, T: 'a> {
+    next: Option<&'a mut Node<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Rust note: mutable references can't be copied like the
+        // shared ones in Iter so the next node has to be taken
+        self.next.take().map(|node| {
+            self.next = node.next.as_deref_mut();
+            self.len -= 1;
+            &mut node.data
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+/// Iterator that pops the elements off of a stack
+pub struct IntoIter<T>(Stack<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for Stack<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Stack<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Stack<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// Pushes every element in order, so the last one ends up on top
+impl<T> Extend<T> for Stack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.push(data);
+        }
+    }
+}
+
+/// Pushes every element in order, so the last one ends up on top
+impl<T> FromIterator<T> for Stack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stack = Stack::new();
+        stack.extend(iter);
+        stack
+    }
+}
+
+/// Rust note: the default drop would drop the head, which drops
+/// its next node and so on, recursing once per node until a long
+/// enough stack overflows the thread's stack. Clearing the stack
+/// unlinks the nodes in a loop instead.
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Stack::new()
+    }
+}
+
+impl<T: Clone> Clone for Stack<T> {
+    fn clone(&self) -> Self {
+        // Push the elements from the bottom up so the clone
+        // ends up in the same order
+        let elements: Vec<&T> = self.iter().collect();
+        elements.into_iter().rev().cloned().collect()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Stack<T> {
+    fn eq(&self, other: &Stack<T>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for Stack<T> {}
+
+/// Formats the stack from the top to the bottom
+impl<T: Debug> Debug for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
 #[test]
+#[allow(clippy::partialeq_to_none)]
 fn test_stack_push_and_pop() {
     let mut stack = Stack::new();
     stack.push(1);
@@ -100,3 +309,81 @@ fn test_stack_push_and_pop() {
     assert!(stack.pop() == Some(1));
     assert!(stack.pop() == None);
 }
+
+#[test]
+fn test_stack_peek() {
+    let mut stack = Stack::new();
+    assert_eq!(stack.peek(), None);
+    assert_eq!(stack.peek_mut(), None);
+
+    stack.push(1);
+    stack.push(2);
+    assert_eq!(stack.peek(), Some(&2));
+
+    if let Some(top) = stack.peek_mut() {
+        *top = 3;
+    }
+    assert_eq!(stack.pop(), Some(3));
+    assert_eq!(stack.peek(), Some(&1));
+}
+
+#[test]
+fn test_stack_len_and_clear() {
+    let mut stack = Stack::new();
+    assert!(stack.is_empty());
+
+    stack.push(1);
+    stack.push(2);
+    assert_eq!(stack.len(), 2);
+    assert!(!stack.is_empty());
+
+    stack.clear();
+    assert_eq!(stack.len(), 0);
+    assert!(stack.is_empty());
+    assert_eq!(stack.pop(), None);
+}
+
+#[test]
+fn test_stack_iterators() {
+    let mut stack: Stack<i32> = vec![1, 2, 3].into_iter().collect();
+
+    assert_eq!(stack.iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+    assert_eq!(stack.iter().len(), 3);
+
+    for data in &mut stack {
+        *data *= 10;
+    }
+    assert_eq!((&stack).into_iter().collect::<Vec<_>>(), vec![&30, &20, &10]);
+    assert_eq!(stack.into_iter().collect::<Vec<_>>(), vec![30, 20, 10]);
+}
+
+#[test]
+fn test_stack_extend() {
+    let mut stack = Stack::new();
+    stack.push(1);
+    stack.extend(vec![2, 3]);
+
+    assert_eq!(stack.len(), 3);
+    assert_eq!(stack.pop(), Some(3));
+}
+
+#[test]
+fn test_stack_traits() {
+    let stack: Stack<i32> = vec![1, 2, 3].into_iter().collect();
+    let clone = stack.clone();
+
+    assert_eq!(stack, clone);
+    assert_eq!(format!("{:?}", clone), "[3, 2, 1]");
+    assert_eq!(Stack::<i32>::default(), Stack::new());
+    assert!(stack != vec![1, 2].into_iter().collect());
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_stack_drop_long() {
+    let mut stack = Stack::new();
+    for i in 0..20_000_000 {
+        stack.push(i);
+    }
+    drop(stack);
+}
@@ -0,0 +1,356 @@
+//! A LRU cache in front of a slower backing store.
+//!
+//! In write through mode every set goes straight to the store,
+//! so the store is always up to date and evicting an entry is
+//! free. In write back mode a set only marks the entry as dirty
+//! and the store is updated when the entry is evicted or when
+//! the cache is flushed, which batches repeated writes to the
+//! same key at the cost of the store lagging behind the cache.
+
+use cache::Cache;
+use lru_cache::{Entry, LRUCache};
+use std::hash::Hash;
+
+/// A key-value store that a StoredCache reads from and writes to
+pub trait BackingStore<K, V> {
+    type Error;
+
+    /// Reads the value for the given key, if there is one
+    fn load(&mut self, k: &K) -> Result<Option<V>, Self::Error>;
+
+    /// Writes a value for the given key
+    fn store(&mut self, k: &K, v: &V) -> Result<(), Self::Error>;
+
+    /// Deletes the given key
+    fn delete(&mut self, k: &K) -> Result<(), Self::Error>;
+}
+
+/// When a StoredCache writes a set value to its store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Write to the store on every set
+    WriteThrough,
+    /// Write to the store when the entry is evicted or flushed
+    WriteBack,
+}
+
+/// A cached value and whether it is newer than the store's value
+#[derive(Clone)]
+struct Slot<V> {
+    val: V,
+    dirty: bool,
+}
+
+/// A LRU cache that loads misses from and writes values to a backing store
+pub struct StoredCache<K, V, B>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          B: BackingStore<K, V>
+{
+    cache: LRUCache<K, Slot<V>>,
+    store: B,
+    mode: WriteMode,
+}
+
+impl<K, V, B> StoredCache<K, V, B>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          B: BackingStore<K, V>
+{
+    /// Create a new cache with the given capacity in front of the store
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize, store: B, mode: WriteMode) -> StoredCache<K, V, B> {
+        StoredCache {
+            cache: LRUCache::new(capacity),
+            store,
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> WriteMode {
+        self.mode
+    }
+
+    /// The backing store, which may be behind the cache in write back mode
+    pub fn store(&self) -> &B {
+        &self.store
+    }
+
+    /// The number of cached values
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Puts a slot in the cache, writing the entry it evicts
+    /// to the store first if that entry is dirty
+    ///
+    /// The cache is left unchanged if the write fails.
+    fn insert(&mut self, k: K, slot: Slot<V>) -> Result<(), B::Error> {
+        if !self.cache.contains(&k) && self.cache.len() == self.cache.capacity() {
+            if let Some((victim_key, victim)) = self.cache.peek_lru() {
+                if victim.dirty {
+                    self.store.store(&victim_key, &victim.val)?;
+                }
+            }
+        }
+
+        self.cache.set(k, slot);
+        Ok(())
+    }
+
+    /// Returns the value for the given key, loading it from
+    /// the store and caching it if it is not cached
+    pub fn get(&mut self, k: K) -> Result<Option<V>, B::Error> {
+        if let Some(slot) = self.cache.get(k) {
+            return Ok(Some(slot.val));
+        }
+
+        match self.store.load(&k)? {
+            Some(v) => {
+                self.insert(k,
+                            Slot {
+                                val: v.clone(),
+                                dirty: false,
+                            })?;
+                Ok(Some(v))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Sets a key value pair, writing it to the store right away
+    /// in write through mode and later in write back mode
+    pub fn set(&mut self, k: K, v: V) -> Result<(), B::Error> {
+        let dirty = match self.mode {
+            WriteMode::WriteThrough => {
+                self.store.store(&k, &v)?;
+                false
+            }
+            WriteMode::WriteBack => true,
+        };
+
+        self.insert(k,
+                    Slot {
+                        val: v,
+                        dirty,
+                    })
+    }
+
+    /// Deletes the key from the store and the cache, returning the
+    /// cached value if there was one
+    pub fn remove(&mut self, k: K) -> Result<Option<V>, B::Error> {
+        self.store.delete(&k)?;
+
+        match self.cache.entry(k) {
+            Entry::Occupied(entry) => Ok(Some(entry.remove().val)),
+            Entry::Vacant(_) => Ok(None),
+        }
+    }
+
+    /// Writes every dirty entry to the store
+    ///
+    /// Entries written before a failed write stay clean, so
+    /// calling flush again only retries the remaining entries.
+    pub fn flush(&mut self) -> Result<(), B::Error> {
+        // Write the least recently used entries first since
+        // they are the closest to being evicted
+        for (k, slot) in self.cache.iter_mut().rev() {
+            if slot.dirty {
+                self.store.store(k, &slot.val)?;
+                slot.dirty = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, B> Drop for StoredCache<K, V, B>
+    where K: Eq + Hash + Copy,
+          V: Clone,
+          B: BackingStore<K, V>
+{
+    /// Flushes the dirty entries, ignoring errors since
+    /// there is no way to report them
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Load(i32),
+        Store(i32, &'static str),
+        Delete(i32),
+    }
+
+    /// An in-memory store that records every call made to it
+    #[derive(Clone)]
+    struct MockStore {
+        data: Rc<RefCell<HashMap<i32, &'static str>>>,
+        calls: Rc<RefCell<Vec<Call>>>,
+        fail_stores: Rc<RefCell<bool>>,
+    }
+
+    impl MockStore {
+        fn new() -> MockStore {
+            MockStore {
+                data: Rc::new(RefCell::new(HashMap::new())),
+                calls: Rc::new(RefCell::new(Vec::new())),
+                fail_stores: Rc::new(RefCell::new(false)),
+            }
+        }
+
+        /// Returns and clears the recorded calls
+        fn take_calls(&self) -> Vec<Call> {
+            self.calls.borrow_mut().drain(..).collect()
+        }
+    }
+
+    impl BackingStore<i32, &'static str> for MockStore {
+        type Error = String;
+
+        fn load(&mut self, k: &i32) -> Result<Option<&'static str>, String> {
+            self.calls.borrow_mut().push(Call::Load(*k));
+            Ok(self.data.borrow().get(k).cloned())
+        }
+
+        fn store(&mut self, k: &i32, v: &&'static str) -> Result<(), String> {
+            self.calls.borrow_mut().push(Call::Store(*k, v));
+            if *self.fail_stores.borrow() {
+                return Err(format!("failed to store {}", k));
+            }
+            self.data.borrow_mut().insert(*k, *v);
+            Ok(())
+        }
+
+        fn delete(&mut self, k: &i32) -> Result<(), String> {
+            self.calls.borrow_mut().push(Call::Delete(*k));
+            self.data.borrow_mut().remove(k);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_through() {
+        let store = MockStore::new();
+        let mut cache = StoredCache::new(2, store.clone(), WriteMode::WriteThrough);
+
+        cache.set(1, "1").unwrap();
+        cache.set(1, "one").unwrap();
+        cache.set(2, "2").unwrap();
+        assert_eq!(store.take_calls(),
+                   vec![Call::Store(1, "1"), Call::Store(1, "one"), Call::Store(2, "2")]);
+
+        // Evicting 1 costs nothing since the store already has it
+        cache.set(3, "3").unwrap();
+        assert_eq!(store.take_calls(), vec![Call::Store(3, "3")]);
+
+        cache.flush().unwrap();
+        drop(cache);
+        assert_eq!(store.take_calls(), vec![]);
+    }
+
+    #[test]
+    fn test_write_back_on_eviction() {
+        let store = MockStore::new();
+        let mut cache = StoredCache::new(2, store.clone(), WriteMode::WriteBack);
+
+        cache.set(1, "1").unwrap();
+        cache.set(1, "one").unwrap();
+        cache.set(2, "2").unwrap();
+        assert_eq!(store.take_calls(), vec![]);
+
+        // Only the last value of 1 is written when it is evicted
+        cache.set(3, "3").unwrap();
+        assert_eq!(store.take_calls(), vec![Call::Store(1, "one")]);
+        assert_eq!(cache.get(1), Ok(Some("one")));
+        assert_eq!(store.take_calls(), vec![Call::Load(1), Call::Store(2, "2")]);
+
+        // 1 was loaded clean, so evicting it does not write it again
+        cache.set(4, "4").unwrap();
+        assert_eq!(store.take_calls(), vec![Call::Store(3, "3")]);
+        cache.set(5, "5").unwrap();
+        assert_eq!(store.take_calls(), vec![]);
+    }
+
+    #[test]
+    fn test_flush() {
+        let store = MockStore::new();
+        let mut cache = StoredCache::new(4, store.clone(), WriteMode::WriteBack);
+        store.data.borrow_mut().insert(1, "1");
+
+        assert_eq!(cache.get(1), Ok(Some("1")));
+        cache.set(2, "2").unwrap();
+        cache.set(3, "3").unwrap();
+        store.take_calls();
+
+        cache.flush().unwrap();
+        assert_eq!(store.take_calls(), vec![Call::Store(2, "2"), Call::Store(3, "3")]);
+
+        // Flushed entries are clean
+        cache.flush().unwrap();
+        assert_eq!(store.take_calls(), vec![]);
+
+        cache.set(3, "three").unwrap();
+        drop(cache);
+        assert_eq!(store.take_calls(), vec![Call::Store(3, "three")]);
+    }
+
+    #[test]
+    fn test_get_caches_loads() {
+        let store = MockStore::new();
+        let mut cache = StoredCache::new(2, store.clone(), WriteMode::WriteBack);
+        store.data.borrow_mut().insert(1, "1");
+
+        assert_eq!(cache.get(1), Ok(Some("1")));
+        assert_eq!(cache.get(1), Ok(Some("1")));
+        assert_eq!(cache.get(2), Ok(None));
+        assert_eq!(store.take_calls(), vec![Call::Load(1), Call::Load(2)]);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_remove() {
+        let store = MockStore::new();
+        let mut cache = StoredCache::new(2, store.clone(), WriteMode::WriteBack);
+
+        cache.set(1, "1").unwrap();
+        assert_eq!(cache.remove(1), Ok(Some("1")));
+        assert_eq!(cache.remove(2), Ok(None));
+        assert_eq!(store.take_calls(), vec![Call::Delete(1), Call::Delete(2)]);
+
+        // The removed dirty value is never written
+        drop(cache);
+        assert_eq!(store.take_calls(), vec![]);
+    }
+
+    #[test]
+    fn test_failed_write_back_keeps_entry() {
+        let store = MockStore::new();
+        let mut cache = StoredCache::new(1, store.clone(), WriteMode::WriteBack);
+
+        cache.set(1, "1").unwrap();
+        *store.fail_stores.borrow_mut() = true;
+        assert_eq!(cache.set(2, "2"), Err(String::from("failed to store 1")));
+        assert_eq!(cache.flush(), Err(String::from("failed to store 1")));
+        store.take_calls();
+
+        *store.fail_stores.borrow_mut() = false;
+        assert_eq!(cache.get(1), Ok(Some("1")));
+        assert_eq!(cache.get(2), Ok(None));
+        cache.flush().unwrap();
+        assert_eq!(store.take_calls(), vec![Call::Load(2), Call::Store(1, "1")]);
+    }
+}
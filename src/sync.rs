@@ -0,0 +1,38 @@
+//! Synchronization primitives used by the lock-free queues.
+//!
+//! Normally these are the std types. When the crate is built with
+//! `--cfg loom` they are replaced by Loom's checked versions so the
+//! queues can be model checked. Loom's UnsafeCell only hands out
+//! raw pointers through closures, so the std version is wrapped to
+//! have the same interface.
+
+#[cfg(loom)]
+pub use loom::cell::UnsafeCell;
+#[cfg(loom)]
+pub use loom::sync::Arc;
+#[cfg(loom)]
+pub use loom::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+#[cfg(not(loom))]
+pub use std::sync::Arc;
+#[cfg(not(loom))]
+pub use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+#[cfg(not(loom))]
+#[derive(Debug)]
+pub struct UnsafeCell<T>(::std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub fn new(data: T) -> UnsafeCell<T> {
+        UnsafeCell(::std::cell::UnsafeCell::new(data))
+    }
+
+    pub fn with<R, F: FnOnce(*const T) -> R>(&self, f: F) -> R {
+        f(self.0.get())
+    }
+
+    pub fn with_mut<R, F: FnOnce(*mut T) -> R>(&self, f: F) -> R {
+        f(self.0.get())
+    }
+}
@@ -0,0 +1,195 @@
+//! A 2Q cache.
+//!
+//! New entries go into a small FIFO queue (A1in). When they fall
+//! out of it, only their keys are remembered in a second FIFO
+//! queue (A1out). An entry that is set again while its key is in
+//! A1out has proven itself and moves into the main LRU list (Am).
+//! Entries used only once never reach Am, so scans only churn
+//! through A1in.
+
+use cache::{Cache, CapacityError};
+use key_list::KeyList;
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A cache that keeps entries used only once apart
+/// from the entries that are used repeatedly
+pub struct TwoQueueCache<K: Eq + Hash + Copy, V> {
+    capacity: usize,
+    /// The size A1in may grow to before it gives up entries
+    in_capacity: usize,
+    /// The number of evicted keys remembered in A1out
+    out_capacity: usize,
+    a1in: KeyList<K>,
+    a1out: KeyList<K>,
+    am: KeyList<K>,
+    values: HashMap<K, V>,
+}
+
+impl<K, V> TwoQueueCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    /// Create a new 2Q cache with the given capacity, giving a
+    /// quarter of it to A1in and remembering half as many
+    /// evicted keys in A1out
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize) -> TwoQueueCache<K, V> {
+        assert!(capacity > 0, "{}", CapacityError);
+
+        TwoQueueCache {
+            capacity,
+            in_capacity: cmp::max(capacity / 4, 1),
+            out_capacity: cmp::max(capacity / 2, 1),
+            a1in: KeyList::new(),
+            a1out: KeyList::new(),
+            am: KeyList::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Create a new 2Q cache with the given capacity or
+    /// return an error if the capacity is zero
+    pub fn try_new(capacity: usize) -> Result<TwoQueueCache<K, V>, CapacityError> {
+        if capacity == 0 {
+            Err(CapacityError)
+        } else {
+            Ok(TwoQueueCache::new(capacity))
+        }
+    }
+
+    /// Makes room for a new entry if the cache is full
+    fn reclaim(&mut self) {
+        if self.values.len() < self.capacity {
+            return;
+        }
+
+        if self.a1in.len() > self.in_capacity || self.am.len() == 0 {
+            let k = self.a1in.pop_back().unwrap();
+            self.values.remove(&k);
+            self.a1out.push_front(k);
+            if self.a1out.len() > self.out_capacity {
+                self.a1out.pop_back();
+            }
+        } else {
+            let k = self.am.pop_back().unwrap();
+            self.values.remove(&k);
+        }
+    }
+}
+
+impl<K, V> Cache<K, V> for TwoQueueCache<K, V>
+    where K: Eq + Hash + Copy,
+          V: Clone
+{
+    fn get(&mut self, k: K) -> Option<V> {
+        // Hits in A1in are left alone since they are likely
+        // to be correlated with the first access
+        if self.am.remove(&k) {
+            self.am.push_front(k);
+        }
+        self.values.get(&k).cloned()
+    }
+
+    fn set(&mut self, k: K, v: V) {
+        if self.values.contains_key(&k) {
+            if self.am.remove(&k) {
+                self.am.push_front(k);
+            }
+        } else if self.a1out.remove(&k) {
+            self.reclaim();
+            self.am.push_front(k);
+        } else {
+            self.reclaim();
+            self.a1in.push_front(k);
+        }
+
+        self.values.insert(k, v);
+    }
+
+    fn contains(&self, k: &K) -> bool {
+        self.values.contains_key(k)
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cache::Cache;
+    use super::*;
+
+    #[test]
+    fn test_new_entries_go_through_a1in() {
+        let mut cache = TwoQueueCache::new(4);
+        for i in 1..6 {
+            cache.set(i, i);
+        }
+
+        // 1 fell out of A1in and is only remembered by key
+        assert!(!cache.contains(&1));
+        assert!(cache.a1out.contains(&1));
+        assert_eq!(cache.am.len(), 0);
+    }
+
+    #[test]
+    fn test_second_set_promotes_to_am() {
+        let mut cache = TwoQueueCache::new(4);
+        for i in 1..6 {
+            cache.set(i, i);
+        }
+        cache.set(1, 10);
+
+        assert!(cache.am.contains(&1));
+        assert!(!cache.a1out.contains(&1));
+        assert_eq!(cache.get(1), Some(10));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_scan_does_not_flush_am() {
+        let mut cache = TwoQueueCache::new(4);
+        for i in 1..6 {
+            cache.set(i, i);
+        }
+        cache.set(1, 1);
+
+        for i in 100..200 {
+            cache.set(i, i);
+        }
+
+        assert_eq!(cache.get(1), Some(1));
+        assert_eq!(cache.len(), 4);
+        assert!(cache.a1out.len() <= 2);
+    }
+
+    #[test]
+    fn test_a1in_hit_does_not_promote() {
+        let mut cache = TwoQueueCache::new(4);
+        cache.set(1, 1);
+        assert_eq!(cache.get(1), Some(1));
+        assert_eq!(cache.get(1), Some(1));
+        assert!(cache.a1in.contains(&1));
+        assert_eq!(cache.am.len(), 0);
+    }
+
+    #[test]
+    fn test_zero_capacity_is_rejected() {
+        assert_eq!(TwoQueueCache::<i32, i32>::try_new(0).err(), Some(CapacityError));
+        assert!(TwoQueueCache::<i32, i32>::try_new(1).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "cache capacity must be at least one")]
+    fn test_new_panics_on_zero_capacity() {
+        TwoQueueCache::<i32, i32>::new(0);
+    }
+}
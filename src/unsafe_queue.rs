@@ -3,60 +3,217 @@
 //! It is more performant than reference counting
 //! but more unsafe because of the unsafe blocks
 //! and the pointer manipulation.
+//!
+//! Every node is turned into a raw pointer with Box::into_raw
+//! as soon as it is allocated and only turned back into a Box
+//! when it is popped or dropped. Both the head and the tail are
+//! raw pointers, so no pointer into the list is ever derived from
+//! a Box or a reference that is later moved or reused, which keeps
+//! the list sound under Miri's aliasing model.
 
+use std::marker::PhantomData;
 use std::ptr;
 
 struct Node<T> {
     elem: T,
-    next: Link<T>,
+    next: *mut Node<T>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
-
 pub struct List<T> {
-    head: Link<T>,
+    head: *mut Node<T>,
     tail: *mut Node<T>, // unsafe pointer
+    len: usize,
+    marker: PhantomData<Box<Node<T>>>,
 }
 
 impl<T> List<T> {
     pub fn new() -> Self {
         List {
-            head: None,
+            head: ptr::null_mut(),
             tail: ptr::null_mut(),
+            len: 0,
+            marker: PhantomData,
         }
     }
 
     pub fn push(&mut self, elem: T) {
-        let mut new_tail = Box::new(Node {
-            elem: elem,
-            next: None,
-        });
-
-        // get unsafe pointer to tail
-        let raw_tail: *mut _ = &mut *new_tail;
+        let new_tail = Box::into_raw(Box::new(Node {
+            elem,
+            next: ptr::null_mut(),
+        }));
 
         if !self.tail.is_null() {
             unsafe {
-                (*self.tail).next = Some(new_tail);
+                (*self.tail).next = new_tail;
             }
         } else {
-            self.head = Some(new_tail);
+            self.head = new_tail;
         }
 
-        self.tail = raw_tail;
+        self.tail = new_tail;
+        self.len += 1;
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        self.head.take().map(|head| {
-            let head = *head;
-            self.head = head.next;
+        if self.head.is_null() {
+            return None;
+        }
 
-            if self.head.is_none() {
-                self.tail = ptr::null_mut();
-            }
+        let head = unsafe { Box::from_raw(self.head) };
+        self.head = head.next;
+
+        if self.head.is_null() {
+            self.tail = ptr::null_mut();
+        }
+
+        self.len -= 1;
+        Some(head.elem)
+    }
+
+    /// Returns the element that the next pop will return
+    pub fn peek(&self) -> Option<&T> {
+        unsafe { self.head.as_ref().map(|node| &node.elem) }
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.head.as_mut().map(|node| &mut node.elem) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// Iterates from the front of the queue to the back
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        List::new()
+    }
+}
 
-            head.elem
-        })
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // Free the nodes in a loop so that dropping a long
+        // list doesn't recurse once per node
+        while self.pop().is_some() {}
+    }
+}
+
+/// Iterator over references to the elements of a list
+pub struct Iter<'a, T: 'a> {
+    next: *mut Node<T>,
+    len: usize,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            self.next.as_ref().map(|node| {
+                self.next = node.next;
+                self.len -= 1;
+                &node.elem
+            })
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// Iterator over mutable references to the elements of a list
+pub struct IterMut<'a, T: 'a> {
+    next: *mut Node<T>,
+    len: usize,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            self.next.as_mut().map(|node| {
+                self.next = node.next;
+                self.len -= 1;
+                &mut node.elem
+            })
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+/// Iterator that pops the elements of a list
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
     }
 }
 
@@ -81,3 +238,96 @@ fn test_basics() {
     assert_eq!(list.pop(), Some(5));
     assert_eq!(list.pop(), None);
 }
+
+#[test]
+fn test_peek() {
+    let mut list = List::new();
+    assert_eq!(list.peek(), None);
+    assert_eq!(list.peek_mut(), None);
+
+    list.push(1);
+    list.push(2);
+    assert_eq!(list.peek(), Some(&1));
+    if let Some(elem) = list.peek_mut() {
+        *elem = 10;
+    }
+    assert_eq!(list.pop(), Some(10));
+    assert_eq!(list.peek(), Some(&2));
+    assert_eq!(list.len(), 1);
+    assert!(!list.is_empty());
+}
+
+#[test]
+fn test_iterators() {
+    let mut list = List::new();
+    for i in 1..4 {
+        list.push(i);
+    }
+
+    assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &3]);
+    assert_eq!(list.iter().len(), 3);
+    for elem in &mut list {
+        *elem *= 10;
+    }
+    assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![10, 20, 30]);
+}
+
+#[test]
+fn test_interleaved_access() {
+    // Mixes every kind of access to the nodes, which is what
+    // used to trip Miri when the tail came from a moved Box
+    let mut list = List::new();
+    list.push(1);
+    list.push(2);
+    list.pop();
+    list.push(3);
+    if let Some(elem) = list.peek_mut() {
+        *elem *= 10;
+    }
+    list.push(4);
+    for elem in list.iter_mut() {
+        *elem += 1;
+    }
+    list.push(5);
+    assert_eq!(list.pop(), Some(21));
+    list.push(6);
+    assert_eq!(list.iter().collect::<Vec<_>>(), vec![&4, &5, &5, &6]);
+    assert_eq!(list.pop(), Some(4));
+    assert_eq!(list.pop(), Some(5));
+    assert_eq!(list.pop(), Some(5));
+    assert_eq!(list.pop(), Some(6));
+    assert_eq!(list.pop(), None);
+    assert!(list.is_empty());
+
+    // The list still works after being emptied
+    list.push(7);
+    assert_eq!(list.peek(), Some(&7));
+}
+
+#[test]
+fn test_drop_releases_elements() {
+    use std::rc::Rc;
+
+    let rc = Rc::new(());
+    {
+        let mut list = List::new();
+        for _ in 0..10 {
+            list.push(rc.clone());
+        }
+        list.pop();
+        let mut iter = list.into_iter();
+        iter.next();
+        assert_eq!(Rc::strong_count(&rc), 9);
+    }
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_drop_long() {
+    let mut list = List::new();
+    for i in 0..20_000_000 {
+        list.push(i);
+    }
+    drop(list);
+}